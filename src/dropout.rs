@@ -0,0 +1,252 @@
+use std::{fmt::Write, path::Path};
+
+use image::Rgba;
+use ndarray::{Array2, Array4, Axis};
+use nifti::NiftiHeader;
+
+use crate::{
+    file,
+    graphics::{self, Colormap},
+    inputs::{Args, ContextInputs},
+    slicer::{Slicer, View},
+    Image,
+};
+
+/// Pixels per volume and per slice of the heatmap
+const CELL_SIZE: u32 = 8;
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+/// Axial slice of a DWI volume whose signal is much lower than in the other volumes.
+struct Outlier {
+    volume: usize,
+    slice: usize,
+    /// Mean signal of the slice, relative to the mean signal of its neighbors
+    ratio: f32,
+    z_score: f32,
+}
+
+/// Detects the signal dropouts of each axial slice of each volume, and saves a heatmap of the
+/// z-scores, a report of the outliers, and a screenshot of the `nb_worst` worst ones.
+pub fn run(
+    args: &Args,
+    header: NiftiHeader,
+    dwi: &Array4<f32>,
+    threshold: f32,
+    nb_worst: usize,
+    format: ReportFormat,
+) {
+    let ratios = ratios(dwi);
+    let z_scores = z_scores(&ratios);
+    let mut outliers = outliers(&ratios, &z_scores, threshold);
+    outliers.sort_by(|a, b| a.z_score.total_cmp(&b.z_score));
+    log::info!("{} slices with a signal dropout", outliers.len());
+
+    file::save_image(
+        heatmap(&z_scores, threshold),
+        &args.output.join("dropout.png"),
+    );
+    let (name, report) = match format {
+        ReportFormat::Csv => ("dropout.csv", csv(&outliers)),
+        ReportFormat::Json => ("dropout.json", json(&outliers)),
+    };
+    save_report(&report, &args.output.join(name));
+
+    let mut graphics = graphics::Context::new(ContextInputs::new(args, &header));
+    for outlier in outliers.iter().take(nb_worst) {
+        let volume = dwi.index_axis(Axis(3), outlier.volume).to_owned();
+        let slicer = Slicer::from_3d_at(header.clone(), volume, View::Superior, outlier.slice);
+        for slice in slicer.slices {
+            let image = graphics.process_slice(&slice);
+            let name = format!("dropout_{}_{}.png", outlier.volume, outlier.slice);
+            file::save_image(image, &args.output.join(name));
+        }
+    }
+}
+
+/// Robust z-scores of the ratios of each slice, across the volumes.
+fn z_scores(ratios: &Array2<f32>) -> Array2<f32> {
+    let mut z_scores = Array2::zeros(ratios.raw_dim());
+    for (slice, ratios) in ratios.axis_iter(Axis(1)).enumerate() {
+        let values: Vec<f32> = ratios.to_vec();
+        let median = median(values.clone());
+        let deviation = median_absolute_deviation(&values, median);
+        for (volume, ratio) in values.iter().enumerate() {
+            z_scores[(volume, slice)] = if deviation > f32::EPSILON {
+                (ratio - median) / deviation
+            } else {
+                0.
+            };
+        }
+    }
+    z_scores
+}
+
+/// Mean signal of each axial slice of each volume, divided by the mean signal of the
+/// adjacent slices of the same volume. `(nb_volumes, nb_slices)`
+fn ratios(dwi: &Array4<f32>) -> Array2<f32> {
+    let (_, _, nb_slices, nb_volumes) = dwi.dim();
+    let means = Array2::from_shape_fn((nb_volumes, nb_slices), |(volume, slice)| {
+        let slice = dwi.slice(ndarray::s![.., .., slice, volume]);
+        slice.mean().unwrap_or(0.)
+    });
+
+    Array2::from_shape_fn((nb_volumes, nb_slices), |(volume, slice)| {
+        let neighbors: Vec<f32> = [slice.checked_sub(1), Some(slice + 1)]
+            .into_iter()
+            .flatten()
+            .filter_map(|neighbor| means.get((volume, neighbor)).copied())
+            .collect();
+        let reference = neighbors.iter().sum::<f32>() / neighbors.len() as f32;
+        if reference > f32::EPSILON {
+            means[(volume, slice)] / reference
+        } else {
+            1. // Empty slices
+        }
+    })
+}
+
+fn outliers(ratios: &Array2<f32>, z_scores: &Array2<f32>, threshold: f32) -> Vec<Outlier> {
+    z_scores
+        .indexed_iter()
+        .filter(|(_, &z_score)| z_score < -threshold)
+        .map(|((volume, slice), &z_score)| Outlier {
+            volume,
+            slice,
+            ratio: ratios[(volume, slice)],
+            z_score,
+        })
+        .collect()
+}
+
+fn median(mut values: Vec<f32>) -> f32 {
+    values.sort_by(f32::total_cmp);
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.
+    } else {
+        values[middle]
+    }
+}
+
+/// Scaled to estimate the standard deviation of normally distributed values.
+fn median_absolute_deviation(values: &[f32], center: f32) -> f32 {
+    1.4826 * median(values.iter().map(|v| (v - center).abs()).collect())
+}
+
+/// One column per volume and one row per slice, the first slice at the bottom. The drops of
+/// signal are bright, and saturate at twice the threshold.
+fn heatmap(z_scores: &Array2<f32>, threshold: f32) -> Image {
+    let (nb_volumes, nb_slices) = z_scores.dim();
+    Image::from_fn(
+        nb_volumes as u32 * CELL_SIZE,
+        nb_slices as u32 * CELL_SIZE,
+        |x, y| {
+            let volume = (x / CELL_SIZE) as usize;
+            let slice = nb_slices - 1 - (y / CELL_SIZE) as usize;
+            let drop = -z_scores[(volume, slice)];
+            let color = Colormap::Hot.color(drop, (0., 2. * threshold));
+            let [r, g, b] = [color.x, color.y, color.z].map(|c| (c * 255.).round() as u8);
+            Rgba([r, g, b, 255])
+        },
+    )
+}
+
+fn csv(outliers: &[Outlier]) -> String {
+    let mut report = String::from("volume,slice,ratio,z_score\n");
+    for outlier in outliers {
+        let Outlier {
+            volume,
+            slice,
+            ratio,
+            z_score,
+        } = outlier;
+        writeln!(report, "{volume},{slice},{ratio},{z_score}").unwrap();
+    }
+    report
+}
+
+fn json(outliers: &[Outlier]) -> String {
+    let entries: Vec<String> = outliers
+        .iter()
+        .map(|outlier| {
+            let Outlier {
+                volume,
+                slice,
+                ratio,
+                z_score,
+            } = outlier;
+            format!(
+                "  {{\"volume\": {volume}, \"slice\": {slice}, \"ratio\": {}, \
+                 \"z_score\": {}}}",
+                json_number(*ratio),
+                json_number(*z_score)
+            )
+        })
+        .collect();
+    if entries.is_empty() {
+        return "[]\n".to_string();
+    }
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+/// JSON has no NaN nor infinity, so the non-finite values are written as `null`.
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+fn save_report(report: &str, path: &Path) {
+    if let Err(error) = std::fs::write(path, report) {
+        panic!("Failed to save the report at {path:?}: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_of_odd_and_even_lengths() {
+        assert_eq!(median(vec![3., 1., 2.]), 2.);
+        assert_eq!(median(vec![4., 1., 3., 2.]), 2.5);
+    }
+
+    #[test]
+    fn scaled_slice_is_the_only_outlier() {
+        // Slightly uneven signal, so that the median absolute deviations aren't zero
+        let mut dwi = Array4::from_shape_fn((2, 2, 6, 8), |(_, _, slice, volume)| {
+            100. + ((slice * 7 + volume * 3) % 5) as f32
+        });
+        dwi.slice_mut(ndarray::s![.., .., 2, 5])
+            .mapv_inplace(|v| v * 0.5);
+
+        let ratios = ratios(&dwi);
+        let z_scores = z_scores(&ratios);
+        assert!(ratios[(5, 2)] < 0.6);
+        let outliers = outliers(&ratios, &z_scores, 3.);
+        assert_eq!(outliers.len(), 1);
+        assert_eq!((outliers[0].volume, outliers[0].slice), (5, 2));
+    }
+
+    #[test]
+    fn json_writes_non_finite_values_as_null() {
+        let outliers = [Outlier {
+            volume: 1,
+            slice: 2,
+            ratio: f32::NAN,
+            z_score: f32::NEG_INFINITY,
+        }];
+        assert_eq!(
+            json(&outliers),
+            "[\n  {\"volume\": 1, \"slice\": 2, \"ratio\": null, \"z_score\": null}\n]\n"
+        );
+    }
+}
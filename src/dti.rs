@@ -0,0 +1,226 @@
+use nalgebra::{DMatrix, DVector, Matrix3, SymmetricEigen, Vector3};
+use ndarray::{Array3, Array4, Axis, Zip};
+
+use crate::file::{Gradients, Volume};
+
+/// The volumes with a smaller b-value are used as b=0 volumes.
+pub const B0_THRESHOLD: f32 = 50.;
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum DtiMap {
+    /// Fractional anisotropy
+    Fa,
+    /// Mean diffusivity
+    Md,
+    /// Principal direction, weighted by the FA
+    Rgb,
+}
+
+/// Scalar and directional maps of the tensors fitted to a DWI.
+pub struct TensorMaps {
+    fa: Array3<f32>,
+    md: Array3<f32>,
+    /// `(x, y, z, 3)` principal eigenvectors, in the axes of the b-vectors
+    v1: Array4<f32>,
+}
+
+impl TensorMaps {
+    /// Linear least squares fit of the log signal. Without mask, the voxels whose mean b=0
+    /// signal isn't positive are skipped.
+    pub fn fit(dwi: &Array4<f32>, gradients: &Gradients, mask: Option<&Array3<bool>>) -> Self {
+        let nb_volumes = dwi.dim().3;
+        if gradients.bvals.len() != nb_volumes {
+            panic!(
+                "The DWI has {nb_volumes} volumes, but there are {} gradients.",
+                gradients.bvals.len()
+            );
+        }
+        let b0_volumes: Vec<usize> = (0..nb_volumes)
+            .filter(|&i| gradients.bvals[i] < B0_THRESHOLD)
+            .collect();
+        if b0_volumes.is_empty() || b0_volumes.len() == nb_volumes {
+            panic!("The DWI must have b=0 and diffusion weighted volumes.");
+        }
+        let (x, y, z, _) = dwi.dim();
+        let mask = mask.cloned().unwrap_or_else(|| {
+            let b0_sum = b0_volumes.iter().fold(Array3::zeros((x, y, z)), |sum, &i| {
+                sum + dwi.index_axis(Axis(3), i)
+            });
+            b0_sum.mapv(|v: f32| v > 0.)
+        });
+        if mask.dim() != (x, y, z) {
+            panic!("The mask must have the same dimensions as the DWI.");
+        }
+
+        let solver = solver(gradients);
+        // Avoids the logarithm of zero
+        let min_signal = dwi
+            .iter()
+            .copied()
+            .filter(|&s| s > 0.)
+            .fold(f32::MAX, f32::min);
+
+        let mut fa = Array3::zeros((x, y, z));
+        let mut md = Array3::zeros((x, y, z));
+        let mut v1 = Array4::zeros((x, y, z, 3));
+
+        Zip::from(dwi.lanes(Axis(3)))
+            .and(&mask)
+            .and(&mut fa)
+            .and(&mut md)
+            .and(v1.lanes_mut(Axis(3)))
+            .for_each(|signal, &inside, fa, md, mut v1| {
+                if !inside {
+                    return;
+                }
+                let log_signal = DVector::from_iterator(
+                    nb_volumes,
+                    signal.iter().map(|s| s.max(min_signal).ln()),
+                );
+                let d = &solver * log_signal;
+                let tensor = Matrix3::new(
+                    d[0], d[3], d[4], //
+                    d[3], d[1], d[5], //
+                    d[4], d[5], d[2],
+                );
+                if !tensor.iter().all(|v| v.is_finite()) {
+                    return;
+                }
+
+                let eigen = SymmetricEigen::new(tensor);
+                let principal = eigen.eigenvalues.imax();
+                let eigenvalues = eigen.eigenvalues.map(|l| l.max(0.));
+                *fa = fractional_anisotropy(&eigenvalues);
+                *md = eigenvalues.mean();
+                for (c, value) in eigen.eigenvectors.column(principal).iter().enumerate() {
+                    v1[c] = *value;
+                }
+            });
+        Self { fa, md, v1 }
+    }
+
+    pub fn volume(self, map: DtiMap) -> Volume {
+        match map {
+            DtiMap::Fa => Volume::Scalar(self.fa),
+            DtiMap::Md => Volume::Scalar(self.md),
+            DtiMap::Rgb => {
+                let mut rgb = self.v1.mapv(f32::abs);
+                for mut channel in rgb.axis_iter_mut(Axis(3)) {
+                    channel *= &self.fa;
+                }
+                Volume::Color(rgb)
+            }
+        }
+    }
+}
+
+/// Pseudo-inverse of the design matrix, which gives Dxx, Dyy, Dzz, Dxy, Dxz, Dyz and the log
+/// of the b=0 signal.
+fn solver(gradients: &Gradients) -> DMatrix<f32> {
+    let rows: Vec<[f32; 7]> = gradients
+        .bvals
+        .iter()
+        .zip(&gradients.bvecs)
+        .map(|(&b, g)| {
+            let g = g.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros);
+            [
+                -b * g.x * g.x,
+                -b * g.y * g.y,
+                -b * g.z * g.z,
+                -2. * b * g.x * g.y,
+                -2. * b * g.x * g.z,
+                -2. * b * g.y * g.z,
+                1.,
+            ]
+        })
+        .collect();
+    let design = DMatrix::from_fn(rows.len(), 7, |i, j| rows[i][j]);
+    design
+        .pseudo_inverse(f32::EPSILON)
+        .expect("The SVD of the design matrix converges")
+}
+
+fn fractional_anisotropy(eigenvalues: &Vector3<f32>) -> f32 {
+    let norm = eigenvalues.norm();
+    if norm <= f32::EPSILON {
+        return 0.;
+    }
+    let [l1, l2, l3] = [eigenvalues[0], eigenvalues[1], eigenvalues[2]];
+    let deviation = ((l1 - l2).powi(2) + (l2 - l3).powi(2) + (l3 - l1).powi(2)).sqrt();
+    (deviation / norm / 2f32.sqrt()).min(1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::file::read_fsl_gradients;
+
+    fn write_temp(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn fit_recovers_a_known_tensor() {
+        let s = std::f32::consts::FRAC_1_SQRT_2;
+        let directions = [
+            Vector3::zeros(),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 1., 0.),
+            Vector3::new(0., 0., 1.),
+            Vector3::new(s, s, 0.),
+            Vector3::new(s, 0., s),
+            Vector3::new(0., s, s),
+            Vector3::new(s, -s, 0.),
+        ];
+        let bvals: Vec<f32> = directions
+            .iter()
+            .map(|g| if g.norm() > 0. { 1000. } else { 0. })
+            .collect();
+        let gradients = Gradients {
+            bvals,
+            bvecs: directions.to_vec(),
+        };
+
+        // Prolate tensor along x
+        let tensor = Matrix3::from_diagonal(&Vector3::new(1.7e-3, 0.3e-3, 0.3e-3));
+        let dwi = Array4::from_shape_fn((1, 1, 1, directions.len()), |(_, _, _, i)| {
+            let g = directions[i];
+            1000. * (-gradients.bvals[i] * g.dot(&(tensor * g))).exp()
+        });
+
+        let maps = TensorMaps::fit(&dwi, &gradients, None);
+        let expected_fa = fractional_anisotropy(&tensor.diagonal());
+        assert!((maps.fa[(0, 0, 0)] - expected_fa).abs() < 1e-3);
+        assert!((maps.md[(0, 0, 0)] - 0.7667e-3).abs() < 1e-6);
+        assert!((maps.v1[(0, 0, 0, 0)].abs() - 1.).abs() < 1e-3);
+    }
+
+    #[test]
+    fn fsl_bvecs_are_read_as_rows_or_columns() {
+        // 4 volumes, since 3 rows of 3 values are always read as rows
+        let bval = write_temp("bval", "0 1000 1000 1000\n");
+        let rows = write_temp("bvec-rows", "0 1 0 0\n0 0 1 0\n0 0 0 1\n");
+        let columns = write_temp("bvec-columns", "0 0 0\n1 0 0\n0 1 0\n0 0 1\n");
+
+        let expected = [Vector3::zeros(), Vector3::x(), Vector3::y(), Vector3::z()];
+        for bvec in [&rows, &columns] {
+            let gradients = read_fsl_gradients(&bval, bvec);
+            assert_eq!(gradients.bvals, [0., 1000., 1000., 1000.]);
+            assert_eq!(gradients.bvecs, expected);
+        }
+        for path in [bval, rows, columns] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn fractional_anisotropy_of_isotropic_and_linear_eigenvalues() {
+        assert_eq!(fractional_anisotropy(&Vector3::new(1., 1., 1.)), 0.);
+        assert!((fractional_anisotropy(&Vector3::new(1., 0., 0.)) - 1.).abs() < 1e-6);
+        assert_eq!(fractional_anisotropy(&Vector3::zeros()), 0.);
+    }
+}
@@ -1,8 +1,11 @@
 use std::path::Path;
 
-use nalgebra::Vector3;
-use ndarray::{Array3, Ix3};
-use nifti::{DataElement, InMemNiftiVolume, IntoNdArray, NiftiHeader, NiftiObject, ReaderOptions};
+use nalgebra::{Matrix3, Point3, Vector3};
+use ndarray::{Array3, Array4, Ix3, Ix4};
+use nifti::{
+    DataElement, InMemNiftiVolume, IntoNdArray, NiftiHeader, NiftiObject, NiftiType, ReaderOptions,
+};
+use rgb::RGB8;
 use trk_io::Reader;
 
 use crate::Image;
@@ -15,6 +18,80 @@ where
     P: AsRef<Path>,
     T: DataElement,
 {
+    let (mut header, mut volume) = open_nifti(path);
+
+    // Fix wrong dimensions on some 3D images and check if the requested dimension is equal to
+    // the actual number of dimensions of the image.
+    if header.dim[header.dim[0] as usize] == 1 {
+        volume = drop_last_dimension(&mut header, volume);
+    }
+
+    let dyn_data = volume.into_ndarray::<T>().unwrap();
+    let data = dyn_data
+        .into_dimensionality::<Ix3>()
+        .expect("Loaded NIfTI image must be a 3D array");
+    (header, data)
+}
+
+/// Input image, sliced as the background of the fibers.
+pub enum Volume {
+    Scalar(Array3<f32>),
+    /// `(x, y, z, 3)` colors or vectors, from a RGB24 or 3 components image
+    Color(Array4<f32>),
+}
+
+/// Read a NIfTI image into a `Volume`. The RGB24 images are converted to colors in [0, 1].
+///
+/// Panics if the image isn't in 3D, or in 4D with 3 components.
+pub fn read_volume<P: AsRef<Path>>(path: P) -> (NiftiHeader, Volume) {
+    let (mut header, mut volume) = open_nifti(path);
+
+    // A trailing dimension of 1 is dropped, like in `read_3d_image`
+    if header.dim[0] > 3 && header.dim[header.dim[0] as usize] == 1 {
+        volume = drop_last_dimension(&mut header, volume);
+    }
+
+    if matches!(header.data_type(), Ok(NiftiType::Rgb24)) {
+        let data = volume
+            .into_ndarray::<RGB8>()
+            .unwrap()
+            .into_dimensionality::<Ix3>()
+            .expect("Loaded RGB NIfTI image must be a 3D array");
+        let (x, y, z) = data.dim();
+        let colors = Array4::from_shape_fn((x, y, z, 3), |(i, j, k, channel)| {
+            let color = data[(i, j, k)];
+            [color.r, color.g, color.b][channel] as f32 / 255.
+        });
+        return (header, Volume::Color(colors));
+    }
+
+    let data = volume.into_ndarray::<f32>().unwrap();
+    let volume = match header.dim[0] {
+        3 => Volume::Scalar(data.into_dimensionality::<Ix3>().unwrap()),
+        4 if header.dim[4] == 3 => Volume::Color(data.into_dimensionality::<Ix4>().unwrap()),
+        _ => panic!("Loaded NIfTI image must be a 3D array, or a 4D array with 3 components"),
+    };
+    (header, volume)
+}
+
+/// Read a 4D NIfTI image, like a tensor or a DWI volume, into a `Array4<T>` object.
+///
+/// Panics if the image isn't in 4D.
+pub fn read_4d_image<P, T>(path: P) -> (NiftiHeader, Array4<T>)
+where
+    P: AsRef<Path>,
+    T: DataElement,
+{
+    let (header, volume) = open_nifti(path);
+    let data = volume
+        .into_ndarray::<T>()
+        .unwrap()
+        .into_dimensionality::<Ix4>()
+        .expect("Loaded NIfTI image must be a 4D array");
+    (header, data)
+}
+
+fn open_nifti<P: AsRef<Path>>(path: P) -> (NiftiHeader, InMemNiftiVolume) {
     let path = path.as_ref();
     if !path.exists() {
         panic!("Image {path:?} doesn't exist.");
@@ -24,21 +101,74 @@ where
         .fix_header(true)
         .read_file(path)
         .expect("NIfTI file has a valid and readable format.");
-    let mut header = nifti_object.header().clone();
-    let mut volume = nifti_object.into_volume();
+    let header = nifti_object.header().clone();
+    (header, nifti_object.into_volume())
+}
 
-    // Fix wrong dimensions on some 3D images and check if the requested dimension is equal to
-    // the actual number of dimensions of the image.
-    if header.dim[header.dim[0] as usize] == 1 {
-        header.dim[0] -= 1;
-        volume = InMemNiftiVolume::from_raw_data(&header, volume.into_raw_data()).unwrap();
+fn drop_last_dimension(header: &mut NiftiHeader, volume: InMemNiftiVolume) -> InMemNiftiVolume {
+    header.dim[0] -= 1;
+    InMemNiftiVolume::from_raw_data(header, volume.into_raw_data()).unwrap()
+}
+
+/// Diffusion gradients of the volumes of a DWI.
+pub struct Gradients {
+    /// In s/mm²
+    pub bvals: Vec<f32>,
+    /// Unit vectors, or zero for the b=0 volumes
+    pub bvecs: Vec<Vector3<f32>>,
+}
+
+/// Reads FSL `.bval` and `.bvec` files. The b-vectors may be stored in 3 rows or 3 columns.
+pub fn read_fsl_gradients<P: AsRef<Path>>(bval_path: P, bvec_path: P) -> Gradients {
+    let bvals: Vec<f32> = read_numbers(bval_path).into_iter().flatten().collect();
+    let rows = read_numbers(bvec_path);
+
+    let bvecs: Vec<Vector3<f32>> = match rows.as_slice() {
+        [x, y, z] if x.len() == bvals.len() && y.len() == x.len() && z.len() == x.len() => (0
+            ..bvals.len())
+            .map(|i| Vector3::new(x[i], y[i], z[i]))
+            .collect(),
+        _ if rows.len() == bvals.len() && rows.iter().all(|row| row.len() == 3) => rows
+            .iter()
+            .map(|row| Vector3::new(row[0], row[1], row[2]))
+            .collect(),
+        _ => panic!("The b-vectors must have 3 components per b-value."),
+    };
+    Gradients { bvals, bvecs }
+}
+
+/// Reads a MRtrix gradient table, with one `x y z b` row per volume.
+pub fn read_mrtrix_gradients<P: AsRef<Path>>(path: P) -> Gradients {
+    let rows = read_numbers(path);
+    if rows.iter().any(|row| row.len() != 4) {
+        panic!("The MRtrix gradient table must have 4 columns.");
     }
+    Gradients {
+        bvals: rows.iter().map(|row| row[3]).collect(),
+        bvecs: rows
+            .iter()
+            .map(|row| Vector3::new(row[0], row[1], row[2]))
+            .collect(),
+    }
+}
 
-    let dyn_data = volume.into_ndarray::<T>().unwrap();
-    let data = dyn_data
-        .into_dimensionality::<Ix3>()
-        .expect("Loaded NIfTI image must be a 3D array");
-    (header, data)
+/// Reads a text file of whitespace-separated numbers, one `Vec` per non-empty line.
+fn read_numbers<P: AsRef<Path>>(path: P) -> Vec<Vec<f32>> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| panic!("Failed to read {path:?}: {error}"));
+    text.lines()
+        .map(|line| {
+            line.split_whitespace()
+                .map(|value| {
+                    value
+                        .parse()
+                        .unwrap_or_else(|_| panic!("{value:?} in {path:?} isn't a number."))
+                })
+                .collect::<Vec<f32>>()
+        })
+        .filter(|values| !values.is_empty())
+        .collect()
 }
 
 /// Creates a TrackVis file reader for further data mapping.
@@ -47,11 +177,32 @@ pub fn fibers_reader<P: AsRef<Path>>(path: P, nifti_header: &NiftiHeader) -> Rea
     if !path.exists() {
         panic!("Fibers {path:?} doesn't exist.");
     }
-    let pixdim = &nifti_header.pixdim;
-
     Reader::new(path)
         .expect("The path exists")
-        .to_voxel_space(Vector3::new(pixdim[1], pixdim[2], pixdim[3]))
+        .to_voxel_space(spacing(nifti_header))
+}
+
+/// Returns the linear part of the affine, converting voxel directions to RAS+ mm.
+pub fn voxel_to_mm(nifti_header: &NiftiHeader) -> Matrix3<f32> {
+    nifti_header.affine::<f32>().fixed_view::<3, 3>(0, 0).into()
+}
+
+/// Converts a RAS+ mm position to the voxel space of the fibers, where voxel `i` spans
+/// `[i, i + 1)`. `mm_to_voxel` is the inverse of [`voxel_to_mm`].
+pub fn world_to_voxel(
+    nifti_header: &NiftiHeader,
+    mm_to_voxel: &Matrix3<f32>,
+    position: Point3<f32>,
+) -> Point3<f32> {
+    let origin: Vector3<f32> = nifti_header.affine::<f32>().fixed_view::<3, 1>(0, 3).into();
+    let index = mm_to_voxel * (position.coords - origin);
+    Point3::from(index) + Vector3::repeat(0.5)
+}
+
+/// Returns the voxel size in mm.
+pub fn spacing(nifti_header: &NiftiHeader) -> Vector3<f32> {
+    let pixdim = &nifti_header.pixdim;
+    Vector3::new(pixdim[1], pixdim[2], pixdim[3])
 }
 
 pub fn save_image(img: Image, output_path: &Path) {
@@ -0,0 +1,119 @@
+use glam::Vec3;
+use nalgebra::{Point3, Vector3};
+use ndarray::Array2;
+use nifti::NiftiHeader;
+
+use crate::{
+    dti::B0_THRESHOLD,
+    file::{self, Gradients},
+    graphics::{self, Colormap, EndpointColoring, EndpointSprites, PointCloud},
+    inputs::{Args, ContextInputs},
+    slicer::{Slice, SliceData, View},
+};
+
+/// Side of the cubic volume where the directions are drawn, in voxels
+const SIZE: u16 = 100;
+
+/// b-values closer than this to the first b-value of a shell are in the same shell
+const SHELL_TOLERANCE: f32 = 100.;
+
+/// Draws each direction of the gradient table as a point on the sphere of its shell, colored
+/// by shell. The spheres radii are proportional to the square root of the b-values, like in
+/// q-space. One image is saved per view. The inputs drawn over the image don't fit the
+/// synthetic volume of the directions, and a free camera would replace the views, so they are
+/// rejected.
+pub fn render(args: &Args, gradients: &Gradients, point_size: f32) {
+    let overlays = [
+        &args.fibers,
+        &args.tensor,
+        &args.peaks,
+        &args.odf,
+        &args.modulation,
+    ];
+    if overlays.iter().any(|path| path.is_some())
+        || !args.include.is_empty()
+        || !args.exclude.is_empty()
+        || !args.include_sphere.is_empty()
+        || !args.exclude_sphere.is_empty()
+    {
+        panic!("The gradients subcommand can't draw fibers, fields, ROIs nor modulation maps.");
+    }
+    if args.azimuth.is_some() || args.elevation.is_some() || !args.crosshair.is_empty() {
+        panic!("The gradients subcommand draws the views, without a free camera nor crosshair.");
+    }
+    let mut inputs = ContextInputs::new(args, &header());
+    inputs.endpoint_sprites = Some(EndpointSprites {
+        size: point_size,
+        coloring: EndpointColoring::End,
+    });
+    inputs.points = Some(points(gradients));
+    let mut graphics = graphics::Context::new(inputs);
+
+    for &view in &args.views {
+        let image = graphics.process_slice(&background(view, args.white));
+
+        let mut write_to = args.output.clone();
+        write_to.push(format!("gradients_{}.png", view.name()));
+        file::save_image(image, &write_to);
+    }
+}
+
+/// Cubic volume of 1 mm voxels.
+fn header() -> NiftiHeader {
+    NiftiHeader {
+        dim: [3, SIZE, SIZE, SIZE, 1, 1, 1, 1],
+        pixdim: [1.; 8],
+        ..Default::default()
+    }
+}
+
+fn points(gradients: &Gradients) -> PointCloud {
+    let mut shells: Vec<f32> = vec![];
+    let mut sorted = gradients.bvals.clone();
+    sorted.sort_by(f32::total_cmp);
+    for b in sorted.into_iter().filter(|&b| b >= B0_THRESHOLD) {
+        if shells
+            .last()
+            .is_none_or(|&shell| b - shell > SHELL_TOLERANCE)
+        {
+            shells.push(b);
+        }
+    }
+    let max_b = shells.last().copied().unwrap_or(1.);
+
+    let center = Point3::from([SIZE as f32 / 2.; 3]);
+    let max_radius = 0.45 * SIZE as f32;
+    let (positions, colors) = gradients
+        .bvals
+        .iter()
+        .zip(&gradients.bvecs)
+        .filter_map(|(&b, bvec)| {
+            let direction = bvec.try_normalize(f32::EPSILON)?;
+            let shell = shells
+                .iter()
+                .rposition(|&shell| b >= shell - f32::EPSILON)?;
+            let radius = max_radius * (shells[shell] / max_b).sqrt();
+            let color: Vector3<f32> = Colormap::Categorical.color(shell as f32, (0., 0.));
+            Some((center + direction * radius, color))
+        })
+        .unzip();
+    PointCloud { positions, colors }
+}
+
+/// Empty slice, on the side of the volume farthest from the viewer so it hides no point. The
+/// views are the only rotations of the scene, since the free camera is rejected.
+fn background(view: View, white: bool) -> Slice {
+    let axis = view.axis() as usize;
+    let mut normal = Vec3::ZERO;
+    normal[axis] = 1.;
+    let toward_viewer = view.rotation().transform_vector3(normal).z > 0.;
+    let index = if toward_viewer { 0 } else { SIZE as usize - 1 };
+
+    let value = if white { 255 } else { 0 };
+    Slice {
+        data: SliceData::Gray(Array2::from_elem((SIZE as usize, SIZE as usize), value)),
+        view,
+        index,
+        depth: index as f32 + 0.5,
+    }
+}
@@ -1,3 +1,5 @@
+use glam::Mat4;
+
 use crate::{slicer::Slice, ContextInputs, Image};
 use {client::Client, parameters::Parameters, pipeline::Pipelines, resources::Resources};
 
@@ -10,7 +12,13 @@ mod pipeline;
 mod resources;
 mod workload;
 
-pub use resources::Coloring;
+pub use client::Blending;
+pub use parameters::Camera;
+pub use resources::{
+    Arrows, Clustering, Coloring, Colormap, Density, DensityWeighting, DirectionEncoding,
+    EndpointColoring, EndpointSprites, Filter, GlyphShape, Light, Measure, OdfField, PeakField,
+    PointCloud, Roi, ShBasis, Subsampling, SubsamplingMethod, TensorField, TensorOrder, Tube,
+};
 
 pub struct Context {
     client: Client,
@@ -23,7 +31,7 @@ impl Context {
     pub fn new(inputs: ContextInputs) -> Self {
         let client = pollster::block_on(Client::new(&inputs));
         let parameters = Parameters::new(&inputs);
-        let res = Resources::new(inputs.fibers_reader, &client);
+        let res = Resources::new(inputs, &client);
 
         Self {
             pipelines: Pipelines::new(&res, &client),
@@ -34,6 +42,16 @@ impl Context {
     }
 
     pub fn process_slice(&mut self, slice: &Slice) -> Image {
-        self.execute_workloads(slice)
+        self.execute_workloads(std::slice::from_ref(slice))
+    }
+
+    /// Draws up to 3 slices in the same scene, hiding each other by depth.
+    pub fn process_scene(&mut self, slices: &[Slice]) -> Image {
+        self.execute_workloads(slices)
+    }
+
+    /// Rotates the volume around its center for the next slices, keeping the camera still.
+    pub fn set_scene_rotation(&mut self, rotation: Mat4) {
+        self.parameters.scene_rotation = rotation;
     }
 }
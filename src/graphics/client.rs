@@ -1,8 +1,12 @@
 use glam::UVec2;
+use nalgebra::Matrix3;
 use wgpu::{Adapter, Device, Features, Queue};
 
-use super::ContextInputs;
-use crate::graphics::resources::{Coloring, COLOR_FORMAT};
+use super::{parameters, ContextInputs};
+use crate::graphics::resources::{
+    uniform::Light, Arrows, Coloring, Density, EndpointSprites, Subsampling, Tube,
+    ACCUMULATION_FORMAT, COLOR_FORMAT, REVEALAGE_FORMAT,
+};
 
 /// Stores handlers related to the user environment and various parameters.
 pub struct Client {
@@ -11,8 +15,56 @@ pub struct Client {
     pub img_size: UVec2,
     pub multisample_count: u32,
     pub streamline_batch_size: usize,
+    pub subsampling: Subsampling,
     pub white_mode: bool,
     pub coloring: Coloring,
+    /// Linear part of the image affine
+    pub voxel_to_mm: Matrix3<f32>,
+    /// Inverse of `voxel_to_mm`
+    pub mm_to_voxel: Matrix3<f32>,
+    /// Width, in mm, of the slab around the slice where the fibers are visible
+    pub slab: Option<f32>,
+    /// Opacity of the fibers hidden by the slice
+    pub behind_opacity: f32,
+    /// Width of the fibers, in pixels
+    pub line_width: f32,
+    /// Shade the thick fibers like tubes
+    pub shading: bool,
+    /// Draw the fibers as lit 3D tubes
+    pub tube: Option<Tube>,
+    pub light: Light,
+    /// Arrowheads showing the direction of the fibers
+    pub arrows: Option<Arrows>,
+    /// Only draw the endpoints of the fibers
+    pub endpoint_sprites: Option<EndpointSprites>,
+    /// Draw the track density map over the slices instead of the fibers
+    pub density: Option<Density>,
+    /// Maximum distance, in voxels, between a streamline and its simplification
+    pub simplify: Option<f32>,
+    /// Opacity of the fibers in front of the slice
+    pub opacity: f32,
+    /// How the transparent fibers are combined
+    pub blending: Blending,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Blending {
+    /// Sums the colors, dense bundles saturate
+    Additive,
+    /// Weighted blended order-independent transparency
+    Weighted,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FiberStyle {
+    /// 1 pixel lines
+    Lines,
+    /// Screen-space quads, `line_width` pixels wide
+    Ribbons,
+    /// 3D meshes
+    Tubes,
+    /// Screen-space disks at both ends of the fibers
+    Endpoints,
 }
 
 impl Client {
@@ -28,18 +80,63 @@ impl Client {
             img_size: inputs.dst_img_size,
             multisample_count: max_multisample_count(&adapter),
             streamline_batch_size: inputs.streamline_batch_size,
+            subsampling: inputs.subsampling,
             white_mode: inputs.white_mode,
             coloring: inputs.coloring,
+            voxel_to_mm: inputs.voxel_to_mm,
+            mm_to_voxel: inputs.mm_to_voxel,
+            slab: inputs.slab,
+            behind_opacity: inputs.behind_opacity,
+            line_width: inputs.line_width,
+            shading: inputs.shading,
+            tube: inputs.tube,
+            light: inputs.light,
+            endpoint_sprites: inputs.endpoint_sprites,
+            arrows: inputs.arrows,
+            density: inputs.density,
+            simplify: inputs.simplify.map(|pixels| {
+                let fit_scale =
+                    parameters::fit_scale(inputs.dst_img_size.as_vec2(), inputs.size_3d.as_vec3());
+                // Exact through the center of the volume with the perspective projection
+                pixels / (fit_scale * inputs.zoom)
+            }),
+            opacity: inputs.opacity,
+            blending: inputs.blending,
         }
     }
+
+    pub fn fiber_style(&self) -> FiberStyle {
+        if self.endpoint_sprites.is_some() {
+            FiberStyle::Endpoints
+        } else if self.tube.is_some() {
+            FiberStyle::Tubes
+        } else if self.line_width > 1. {
+            FiberStyle::Ribbons
+        } else {
+            FiberStyle::Lines
+        }
+    }
+
+    /// Blending of the fibers in front of the slice, `None` when they are opaque.
+    pub fn transparency(&self) -> Option<Blending> {
+        (self.opacity < 1.).then_some(self.blending)
+    }
 }
 
+/// Highest sample count supported by all the multisampled render targets.
 fn max_multisample_count(adapter: &Adapter) -> u32 {
-    adapter
-        .get_texture_format_features(COLOR_FORMAT)
-        .flags
-        .supported_sample_counts()
+    let supported = |format| {
+        adapter
+            .get_texture_format_features(format)
+            .flags
+            .supported_sample_counts()
+    };
+    let accumulation = supported(ACCUMULATION_FORMAT);
+    let revealage = supported(REVEALAGE_FORMAT);
+
+    supported(COLOR_FORMAT)
         .into_iter()
+        .filter(|count| accumulation.contains(count) && revealage.contains(count))
         .max()
         .expect("4x is always supported")
 }
@@ -1,29 +1,72 @@
-use glam::{vec2, Mat4, Vec2, Vec3};
+use glam::{vec2, vec3, Mat4, Vec2, Vec3};
 
 use super::ContextInputs;
+use crate::slicer::Slice;
+
+/// Orbit of the camera around the center of the volume, in degrees. An azimuth of 0 looks
+/// from posterior, 90 from the left and 180 from anterior. An elevation of 90 looks from
+/// superior.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub azimuth: f32,
+    pub elevation: f32,
+}
+
+impl Camera {
+    pub fn rotation(&self) -> Mat4 {
+        Mat4::from_rotation_x((self.elevation - 90.).to_radians())
+            * Mat4::from_rotation_z(self.azimuth.to_radians())
+    }
+}
 
 pub struct Parameters {
-    pub fit_scale: f32,
     pub tractogram_alignment: Mat4,
     pub tractogram_projection: Mat4,
+    /// Replaces the rotations of the views
+    pub camera_rotation: Option<Mat4>,
+    /// Rotates the volume around its center, before the camera
+    pub scene_rotation: Mat4,
+    /// Voxel size in mm
+    pub spacing: Vec3,
 }
 
 impl Parameters {
     pub fn new(inputs: &ContextInputs) -> Self {
         let (dst_size, size_3d) = (inputs.dst_img_size.as_vec2(), inputs.size_3d.as_vec3());
         let fit_scale = fit_scale(dst_size, size_3d);
+        let scaled_size_3d = fit_scale * size_3d;
+        // Any rotation of the volume stays within its bounding sphere
+        let depth = match inputs.camera {
+            Some(_) => scaled_size_3d.length() / 2.,
+            None => scaled_size_3d.max_element() / 2.,
+        };
+        let projection = match inputs.field_of_view {
+            Some(field_of_view) => perspective_projection(dst_size, depth, field_of_view),
+            None => tractogram_projection(dst_size, depth),
+        };
 
         Self {
-            fit_scale,
-            tractogram_projection: tractogram_projection(dst_size, fit_scale * size_3d),
+            tractogram_projection: Mat4::from_scale(vec3(inputs.zoom, inputs.zoom, 1.))
+                * projection,
             tractogram_alignment: tractogram_alignment(fit_scale, size_3d),
+            camera_rotation: inputs.camera.map(|camera| camera.rotation()),
+            scene_rotation: Mat4::IDENTITY,
+            spacing: inputs.spacing,
         }
     }
+
+    /// Rotation of the scene, from the camera or the view of the slice.
+    pub fn view_rotation(&self, slice: &Slice) -> Mat4 {
+        self.camera_rotation
+            .unwrap_or_else(|| slice.view.rotation())
+            * self.scene_rotation
+    }
 }
 
 /// Calculates the maximum scaling factor that fits within boundaries,
-/// maintains the aspect ratio, and ensures uniformity across all three axes.
-fn fit_scale(dst_size: Vec2, size_3d: Vec3) -> f32 {
+/// maintains the aspect ratio, and ensures uniformity across all three axes. It is the
+/// number of pixels per voxel.
+pub fn fit_scale(dst_size: Vec2, size_3d: Vec3) -> f32 {
     let max_size = vec2(size_3d.x, size_3d.z).max(vec2(size_3d.y, size_3d.y));
     (dst_size / max_size).min_element()
 }
@@ -35,8 +78,21 @@ fn tractogram_alignment(fit_scale: f32, size_3d: Vec3) -> Mat4 {
 
 /// Creates an orthographic projection matrix. This is used to change the basis from
 /// voxel space to screen space and also defines the depth range.
-fn tractogram_projection(dst_size: Vec2, scaled_size_3d: Vec3) -> Mat4 {
+fn tractogram_projection(dst_size: Vec2, depth: f32) -> Mat4 {
     let half = dst_size / 2.;
-    let depth = scaled_size_3d.max_element() / 2.;
     Mat4::orthographic_rh(-half.x, half.x, -half.y, half.y, -depth, depth)
 }
+
+/// Moves the camera back so that the plane through the center of the volume has the same
+/// size as with the orthographic projection. `field_of_view` is vertical, in degrees.
+fn perspective_projection(dst_size: Vec2, depth: f32, field_of_view: f32) -> Mat4 {
+    let field_of_view = field_of_view.clamp(1., 170.).to_radians();
+    let distance = dst_size.y / 2. / (field_of_view / 2.).tan();
+    let near = (distance - depth).max(distance / 100.);
+    Mat4::perspective_rh(
+        field_of_view,
+        dst_size.x / dst_size.y,
+        near,
+        distance + depth,
+    ) * Mat4::from_translation(vec3(0., 0., -distance))
+}
@@ -1,35 +1,103 @@
 use wgpu::{
-    BindGroupLayout, ColorTargetState, CompareFunction, DepthStencilState, Device,
-    MultisampleState, RenderPipeline,
+    BindGroupLayout, BlendComponent, BlendFactor, BlendState, ColorTargetState, ColorWrites,
+    CompareFunction, DepthStencilState, Device, MultisampleState, RenderPipeline,
 };
 
 use super::{
-    resources::{vertex::Vertex, Resources, COLOR_FORMAT, DEPTH_FORMAT},
+    client::{Blending, FiberStyle},
+    resources::{
+        vertex::Vertex, Resources, ACCUMULATION_FORMAT, COLOR_FORMAT, DEPTH_FORMAT,
+        REVEALAGE_FORMAT,
+    },
     Client,
 };
 
-use state::PipelineState;
+use state::{PipelineState, RenderMode};
 
 mod state;
 
 pub struct Pipelines {
     pub resampling: RenderPipeline,
     pub streamline: Option<RenderPipeline>,
+    pub streamline_behind: Option<RenderPipeline>,
+    pub composite: Option<RenderPipeline>,
+    pub overlay: Option<RenderPipeline>,
+    pub glyph: Option<RenderPipeline>,
+    pub peaks: Option<RenderPipeline>,
+    pub odf: Option<RenderPipeline>,
+    pub points: Option<RenderPipeline>,
     // pub post_processing: RenderPipeline,
 }
 
 impl Pipelines {
     pub fn new(res: &Resources, client: &Client) -> Self {
-        let streamline =
-            (!res.fibers.is_empty()).then(|| create_pipeline(state::streamline(), res, client));
+        let has_fibers = !res.fibers.is_empty();
+        let mode = fibers_mode(client);
+
+        let streamline = has_fibers.then(|| fibers_pipeline(mode, res, client));
+        let streamline_behind = (has_fibers && client.behind_opacity > 0.)
+            .then(|| fibers_pipeline(RenderMode::Behind, res, client));
+        let composite = res
+            .transparency_targets
+            .is_some()
+            .then(|| create_pipeline(state::composite(), res, client));
+
+        let overlay = res
+            .density_map
+            .is_some()
+            .then(|| create_pipeline(state::overlay(), res, client));
+
+        let glyph = res
+            .tensor_field
+            .is_some()
+            .then(|| create_pipeline(state::glyph(), res, client));
+
+        let peaks = res
+            .peak_field
+            .is_some()
+            .then(|| create_pipeline(state::peaks(), res, client));
+
+        let odf = res
+            .odf_field
+            .is_some()
+            .then(|| create_pipeline(state::odf(), res, client));
+
+        let points = res
+            .point_sprites
+            .is_some()
+            .then(|| create_pipeline(state::sprite(), res, client));
 
         Self {
             resampling: create_pipeline(state::resampling(), res, client),
             streamline,
+            streamline_behind,
+            composite,
+            overlay,
+            glyph,
+            peaks,
+            odf,
+            points,
         }
     }
 }
 
+fn fibers_mode(client: &Client) -> RenderMode {
+    match client.transparency() {
+        None => RenderMode::Opaque,
+        Some(Blending::Additive) => RenderMode::Additive,
+        Some(Blending::Weighted) => RenderMode::WeightedBlended,
+    }
+}
+
+fn fibers_pipeline(mode: RenderMode, res: &Resources, client: &Client) -> RenderPipeline {
+    match client.fiber_style() {
+        FiberStyle::Lines => create_pipeline(state::streamline().with_mode(mode), res, client),
+        FiberStyle::Ribbons => create_pipeline(state::ribbon().with_mode(mode), res, client),
+        FiberStyle::Tubes => create_pipeline(state::tube().with_mode(mode), res, client),
+        FiberStyle::Endpoints => create_pipeline(state::sprite().with_mode(mode), res, client),
+    }
+}
+
 fn create_pipeline<V: Vertex>(
     state: PipelineState<V>,
     res: &Resources,
@@ -45,20 +113,29 @@ fn create_pipeline<V: Vertex>(
         buffers: &[V::buffer_layout(&vertex_attributes)],
         compilation_options: Default::default(),
     };
+    let fragment_entry_point = match state.mode {
+        RenderMode::WeightedBlended => "fragment_oit",
+        RenderMode::Overlay => "fragment_overlay",
+        _ => "fragment",
+    };
     let fragment_state = wgpu::FragmentState {
         module,
-        entry_point: "fragment",
-        targets: &[Some(color_target())],
+        entry_point: fragment_entry_point,
+        targets: &color_targets(state.mode),
         compilation_options: Default::default(),
     };
+    let multisample_count = match state.mode {
+        RenderMode::Composite => 1,
+        _ => client.multisample_count,
+    };
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: label!("{}Pipeline", state.name),
         layout: Some(&layout(state.name, state.bindings, res, device)),
         vertex: vertex_state,
         fragment: Some(fragment_state),
         primitive: state.primitive,
-        depth_stencil: Some(depth_stencil(state.use_depth_test)),
-        multisample: multisample(client.multisample_count),
+        depth_stencil: depth_stencil(state.mode),
+        multisample: multisample(multisample_count),
         multiview: None,
     })
 }
@@ -88,27 +165,70 @@ fn layout(
     })
 }
 
-fn color_target() -> ColorTargetState {
-    ColorTargetState {
-        format: COLOR_FORMAT,
-        blend: Some(wgpu::BlendState::REPLACE),
-        write_mask: wgpu::ColorWrites::ALL,
+fn color_targets(mode: RenderMode) -> Vec<Option<ColorTargetState>> {
+    let target = |format, blend, write_mask| {
+        Some(ColorTargetState {
+            format,
+            blend: Some(blend),
+            write_mask,
+        })
+    };
+    let constant_blend = |src_factor, dst_factor| {
+        let component = BlendComponent {
+            src_factor,
+            dst_factor,
+            operation: wgpu::BlendOperation::Add,
+        };
+        BlendState {
+            color: component,
+            alpha: component,
+        }
+    };
+
+    match mode {
+        RenderMode::Opaque => vec![target(COLOR_FORMAT, BlendState::REPLACE, ColorWrites::ALL)],
+        RenderMode::Behind => {
+            let blend = constant_blend(BlendFactor::Constant, BlendFactor::OneMinusConstant);
+            vec![target(COLOR_FORMAT, blend, ColorWrites::ALL)]
+        }
+        RenderMode::Additive => {
+            let blend = constant_blend(BlendFactor::Constant, BlendFactor::One);
+            vec![target(COLOR_FORMAT, blend, ColorWrites::ALL)]
+        }
+        RenderMode::WeightedBlended => {
+            let accumulation = constant_blend(BlendFactor::Constant, BlendFactor::One);
+            let revealage = constant_blend(BlendFactor::Zero, BlendFactor::OneMinusConstant);
+            vec![
+                target(ACCUMULATION_FORMAT, accumulation, ColorWrites::ALL),
+                target(REVEALAGE_FORMAT, revealage, ColorWrites::RED),
+            ]
+        }
+        RenderMode::Overlay | RenderMode::Composite => {
+            vec![target(
+                COLOR_FORMAT,
+                BlendState::ALPHA_BLENDING,
+                ColorWrites::ALL,
+            )]
+        }
     }
 }
 
-fn depth_stencil(active: bool) -> DepthStencilState {
-    let depth_compare = if active {
-        CompareFunction::LessEqual
-    } else {
-        CompareFunction::Always
+fn depth_stencil(mode: RenderMode) -> Option<DepthStencilState> {
+    let (depth_compare, depth_write_enabled) = match mode {
+        RenderMode::Opaque => (CompareFunction::LessEqual, true),
+        RenderMode::Behind => (CompareFunction::Greater, false),
+        RenderMode::Additive | RenderMode::WeightedBlended | RenderMode::Overlay => {
+            (CompareFunction::LessEqual, false)
+        }
+        RenderMode::Composite => return None,
     };
-    DepthStencilState {
+    Some(DepthStencilState {
         format: DEPTH_FORMAT,
-        depth_write_enabled: active,
+        depth_write_enabled,
         depth_compare,
         stencil: wgpu::StencilState::default(),
         bias: wgpu::DepthBiasState::default(),
-    }
+    })
 }
 
 fn multisample(count: u32) -> MultisampleState {
@@ -0,0 +1,25 @@
+@group(0) @binding(0) var accumulation_texture: texture_2d<f32>;
+@group(0) @binding(1) var revealage_texture: texture_2d<f32>;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) uv: vec2f,
+};
+
+@vertex
+fn vertex(in: VertexInput) -> @builtin(position) vec4f {
+    return vec4f(in.position.xy, 0., 1.);
+}
+
+@fragment
+fn fragment(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    let texel = vec2i(position.xy);
+    let revealage = textureLoad(revealage_texture, texel, 0).r;
+    if revealage >= 1. {
+        discard; // No fiber
+    }
+    let accumulation = textureLoad(accumulation_texture, texel, 0);
+    let color = accumulation.rgb / max(accumulation.a, 1e-5);
+
+    return vec4f(color, 1. - revealage);
+}
@@ -0,0 +1,82 @@
+@group(0) @binding(0) var<uniform> transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> lighting: Lighting;
+
+// Must match `GLYPH_VERTEX_COUNT`
+const STACKS: u32 = 8u;
+const SLICES: u32 = 16u;
+const PI: f32 = 3.14159265;
+
+struct VertexInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) center: vec3f,
+    @location(1) transform_x: vec3f,
+    @location(2) transform_y: vec3f,
+    @location(3) transform_z: vec3f,
+    @location(4) normal_x: vec3f,
+    @location(5) normal_y: vec3f,
+    @location(6) normal_z: vec3f,
+    @location(7) shape: vec3f,
+    @location(8) color: vec3f,
+};
+
+struct FragmentInput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec3f,
+    // In view space
+    @location(1) normal: vec3f,
+};
+
+fn signed_pow(x: f32, exponent: f32) -> f32 {
+    return sign(x) * pow(abs(x), exponent);
+}
+
+// Kindlmann's superquadric, with its symmetry axis along x (linear) or z (planar).
+// Exponents of 1 give a sphere.
+fn superquadric(theta: f32, phi: f32, alpha: f32, beta: f32, linear: bool) -> vec3f {
+    let cos_theta = signed_pow(cos(theta), alpha);
+    let sin_theta = signed_pow(sin(theta), alpha);
+    let cos_phi = signed_pow(cos(phi), beta);
+    let sin_phi = signed_pow(sin(phi), beta);
+
+    if linear {
+        return vec3f(cos_phi, -sin_theta * sin_phi, cos_theta * sin_phi);
+    }
+    return vec3f(cos_theta * sin_phi, sin_theta * sin_phi, cos_phi);
+}
+
+@vertex
+fn vertex(in: VertexInput) -> FragmentInput {
+    var corners = array<vec2u, 6>(
+        vec2u(0u, 0u), vec2u(1u, 0u), vec2u(1u, 1u),
+        vec2u(0u, 0u), vec2u(1u, 1u), vec2u(0u, 1u),
+    );
+    let quad = in.index / 6u;
+    let corner = corners[in.index % 6u];
+    let theta = 2. * PI * f32(quad % SLICES + corner.x) / f32(SLICES);
+    let phi = PI * f32(quad / SLICES + corner.y) / f32(STACKS);
+
+    let alpha = in.shape.x;
+    let beta = in.shape.y;
+    let linear = in.shape.z > 0.5;
+    let point = superquadric(theta, phi, alpha, beta, linear);
+    let normal = superquadric(theta, phi, 2. - alpha, 2. - beta, linear);
+
+    let glyph_transform = mat3x3<f32>(in.transform_x, in.transform_y, in.transform_z);
+    let normal_transform = mat3x3<f32>(in.normal_x, in.normal_y, in.normal_z);
+    let position = in.center + glyph_transform * point;
+
+    return FragmentInput(
+        transform * vec4f(position, 1.),
+        in.color,
+        (lighting.rotation * vec4f(normal_transform * normal, 0.)).xyz,
+    );
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4f {
+    var normal = normalize(in.normal);
+    if dot(normal, vec3f(0., 0., 1.)) < 0. {
+        normal = -normal; // Mirrored transform, or pole of a superquadric
+    }
+    return vec4f(blinn_phong(in.color, normal, lighting), 1.);
+}
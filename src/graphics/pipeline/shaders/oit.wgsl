@@ -0,0 +1,20 @@
+
+// Weighted blended order-independent transparency, from McGuire and Bavoil (2013).
+// Appended to the fibers shaders, which must define `FragmentInput` and `shade`.
+// The opacity is applied by the blend constant.
+
+struct OitOutput {
+    @location(0) accumulation: vec4f,
+    @location(1) revealage: vec4f,
+};
+
+@fragment
+fn fragment_oit(in: FragmentInput) -> OitOutput {
+    let color = shade(in);
+
+    // Favors the fragments closest to the viewer
+    let depth = in.clip_position.z;
+    let weight = max(1e-2, 3e3 * pow(1. - depth, 3.));
+
+    return OitOutput(vec4f(color * weight, weight), vec4f(1.));
+}
@@ -1,8 +1,9 @@
 @group(0) @binding(0) var source_texture: texture_2d<f32>;
 @group(0) @binding(1) var linear_sampler: sampler;
+@group(1) @binding(0) var<uniform> transform: mat4x4<f32>;
 
 struct VertexInput {
-    @location(0) canon: vec2f,
+    @location(0) position: vec3f,
     @location(1) uv: vec2f,
 };
 
@@ -13,11 +14,16 @@ struct FragmentInput {
 
 @vertex
 fn vertex(in: VertexInput) -> FragmentInput {
-    return FragmentInput(vec4f(in.canon, 0., 1.), in.uv);
+    return FragmentInput(transform * vec4f(in.position, 1.), in.uv);
 }
 
 @fragment
 fn fragment(in: FragmentInput) -> @location(0) vec4f {
-    let value = textureSample(source_texture, linear_sampler, in.uv).r;
-    return vec4f(vec3f(value), 1.);
+    let color = textureSample(source_texture, linear_sampler, in.uv).rgb;
+    return vec4f(color, 1.);
+}
+
+@fragment
+fn fragment_overlay(in: FragmentInput) -> @location(0) vec4f {
+    return textureSample(source_texture, linear_sampler, in.uv);
 }
@@ -0,0 +1,68 @@
+@group(0) @binding(0) var<uniform> transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> slab: Slab;
+@group(2) @binding(0) var<uniform> ribbon: Ribbon;
+
+struct VertexInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) start: vec3f,
+    @location(1) start_color: vec3f,
+    @location(2) end: vec3f,
+    @location(3) end_color: vec3f,
+};
+
+struct FragmentInput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec3f,
+    @location(1) position: vec3f,
+    // From -1 to 1 across the ribbon
+    @location(2) side: f32,
+};
+
+@vertex
+fn vertex(in: VertexInput) -> FragmentInput {
+    // Two triangles, as (along the segment, across the segment)
+    var corners = array<vec2f, 6>(
+        vec2f(0., -1.), vec2f(1., -1.), vec2f(1., 1.),
+        vec2f(0., -1.), vec2f(1., 1.), vec2f(0., 1.),
+    );
+    let corner = corners[in.index];
+
+    let start = transform * vec4f(in.start, 1.);
+    let end = transform * vec4f(in.end, 1.);
+
+    // Direction and normal in pixels
+    let screen_vector = (end.xy / end.w - start.xy / start.w) * ribbon.viewport;
+    var direction = vec2f(1., 0.);
+    if length(screen_vector) > 1e-6 {
+        direction = normalize(screen_vector);
+    }
+    let normal = vec2f(-direction.y, direction.x);
+
+    // The ends are extended by half the width to close the gaps between the segments
+    let offset = (normal * corner.y + direction * (corner.x * 2. - 1.)) * ribbon.half_width;
+    let clip_position = mix(start, end, corner.x);
+
+    return FragmentInput(
+        clip_position + vec4f(offset * 2. / ribbon.viewport * clip_position.w, 0., 0.),
+        mix(in.start_color, in.end_color, corner.x),
+        mix(in.start, in.end, corner.x),
+        corner.y,
+    );
+}
+
+fn shade(in: FragmentInput) -> vec3f {
+    if abs(dot(in.position, slab.normal) - slab.depth) > slab.half_width {
+        discard;
+    }
+    var color = in.color;
+    if ribbon.shading > 0.5 {
+        // Lambertian tube lit from the viewer
+        color *= 0.3 + 0.7 * sqrt(max(1. - in.side * in.side, 0.));
+    }
+    return color;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4f {
+    return vec4f(shade(in), 1.);
+}
@@ -0,0 +1,52 @@
+@group(0) @binding(0) var<uniform> transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> slab: Slab;
+@group(2) @binding(0) var<uniform> ribbon: Ribbon;
+
+struct VertexInput {
+    @builtin(vertex_index) index: u32,
+    @location(0) center: vec3f,
+    @location(1) color: vec3f,
+};
+
+struct FragmentInput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec3f,
+    @location(1) position: vec3f,
+    // From -1 to 1 across the sprite
+    @location(2) corner: vec2f,
+};
+
+@vertex
+fn vertex(in: VertexInput) -> FragmentInput {
+    var corners = array<vec2f, 6>(
+        vec2f(-1., -1.), vec2f(1., -1.), vec2f(1., 1.),
+        vec2f(-1., -1.), vec2f(1., 1.), vec2f(-1., 1.),
+    );
+    let corner = corners[in.index];
+
+    let center = transform * vec4f(in.center, 1.);
+    let offset = corner * ribbon.half_width * 2. / ribbon.viewport * center.w;
+
+    return FragmentInput(center + vec4f(offset, 0., 0.), in.color, in.center, corner);
+}
+
+fn shade(in: FragmentInput) -> vec3f {
+    if abs(dot(in.position, slab.normal) - slab.depth) > slab.half_width {
+        discard;
+    }
+    let radius_squared = dot(in.corner, in.corner);
+    if radius_squared > 1. {
+        discard; // Outside the disk
+    }
+    var color = in.color;
+    if ribbon.shading > 0.5 {
+        // Lambertian sphere lit from the viewer
+        color *= 0.3 + 0.7 * sqrt(1. - radius_squared);
+    }
+    return color;
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4f {
+    return vec4f(shade(in), 1.);
+}
@@ -1,4 +1,5 @@
 @group(0) @binding(0) var<uniform> transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> slab: Slab;
 
 struct VertexInput {
     @location(0) position: vec3<f32>,
@@ -8,17 +9,26 @@ struct VertexInput {
 struct FragmentInput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) color: vec3<f32>,
+    @location(1) position: vec3<f32>,
 };
 
 @vertex
 fn vertex(in: VertexInput) -> FragmentInput {
     return FragmentInput(
         transform * vec4<f32>(in.position, 1.),
-        in.color
+        in.color,
+        in.position
     );
 }
 
+fn shade(in: FragmentInput) -> vec3f {
+    if abs(dot(in.position, slab.normal) - slab.depth) > slab.half_width {
+        discard;
+    }
+    return in.color;
+}
+
 @fragment
 fn fragment(in: FragmentInput) -> @location(0) vec4f {
-    return vec4f(in.color, 1.);
+    return vec4f(shade(in), 1.);
 }
@@ -0,0 +1,39 @@
+@group(0) @binding(0) var<uniform> transform: mat4x4<f32>;
+@group(1) @binding(0) var<uniform> slab: Slab;
+@group(2) @binding(0) var<uniform> lighting: Lighting;
+
+struct VertexInput {
+    @location(0) position: vec3f,
+    @location(1) normal: vec3f,
+    @location(2) color: vec3f,
+};
+
+struct FragmentInput {
+    @builtin(position) clip_position: vec4f,
+    @location(0) color: vec3f,
+    @location(1) position: vec3f,
+    // In view space
+    @location(2) normal: vec3f,
+};
+
+@vertex
+fn vertex(in: VertexInput) -> FragmentInput {
+    return FragmentInput(
+        transform * vec4f(in.position, 1.),
+        in.color,
+        in.position,
+        (lighting.rotation * vec4f(in.normal, 0.)).xyz,
+    );
+}
+
+fn shade(in: FragmentInput) -> vec3f {
+    if abs(dot(in.position, slab.normal) - slab.depth) > slab.half_width {
+        discard;
+    }
+    return blinn_phong(in.color, normalize(in.normal), lighting);
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4f {
+    return vec4f(shade(in), 1.);
+}
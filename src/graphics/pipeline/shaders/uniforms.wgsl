@@ -0,0 +1,35 @@
+// Uniforms shared by the shaders, mirroring the `#[repr(C)]` structs of `uniform.rs`, and the
+// functions using them. Prepended to the shaders that use them.
+
+struct Slab {
+    normal: vec3f,
+    depth: f32,
+    half_width: f32,
+};
+
+struct Ribbon {
+    viewport: vec2f,
+    half_width: f32,
+    shading: f32,
+};
+
+struct Lighting {
+    rotation: mat4x4<f32>,
+    light: vec3f,
+    ambient: f32,
+    specular: f32,
+    shininess: f32,
+};
+
+// Blinn-Phong shading of `color`, with the light direction in view space. The viewer is
+// distant, also with the perspective projection.
+fn blinn_phong(color: vec3f, normal: vec3f, lighting: Lighting) -> vec3f {
+    let half_vector = normalize(lighting.light + vec3f(0., 0., 1.));
+
+    let diffuse = max(dot(normal, lighting.light), 0.);
+    let specular = pow(max(dot(normal, half_vector), 0.), lighting.shininess);
+
+    let shaded = color * (lighting.ambient + (1. - lighting.ambient) * diffuse)
+        + vec3f(lighting.specular * specular);
+    return min(shaded, vec3f(1.));
+}
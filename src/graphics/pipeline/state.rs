@@ -2,7 +2,9 @@ use std::marker::PhantomData;
 
 use wgpu::{FrontFace, PolygonMode, PrimitiveState, PrimitiveTopology};
 
-use crate::graphics::resources::vertex::{FiberVertex, ImageVertex, Vertex};
+use crate::graphics::resources::vertex::{
+    FiberVertex, GlyphVertex, ImageVertex, SegmentVertex, SpriteVertex, TubeVertex, Vertex,
+};
 
 pub struct PipelineState<'a, V: Vertex> {
     pub name: &'a str,
@@ -13,12 +15,40 @@ pub struct PipelineState<'a, V: Vertex> {
     /// Specifies the bind groups to the pipeline layout
     pub bindings: Vec<&'a str>,
 
-    pub use_depth_test: bool,
+    pub mode: RenderMode,
     pub primitive: PrimitiveState,
 
     pub _vertex_type: PhantomData<V>,
 }
 
+/// Defines the targets, the blending and the depth test of a pipeline.
+#[derive(Clone, Copy)]
+pub enum RenderMode {
+    /// Keeps the closest fragments and writes their depth
+    Opaque,
+    /// Keeps the fragments behind the current depth, without writing it. They are blended
+    /// using the blend constant as opacity.
+    Behind,
+    /// Keeps the fragments in front of the current depth, without writing it. They are added
+    /// using the blend constant as opacity.
+    Additive,
+    /// Keeps the fragments in front of the current depth, without writing it. They are
+    /// accumulated to the weighted blended order-independent transparency targets, using the
+    /// `fragment_oit` entry point and the blend constant as opacity.
+    WeightedBlended,
+    /// Blends an image over the slice, at its depth, using the `fragment_overlay` entry point.
+    Overlay,
+    /// Blends the resolved transparency targets over the final image, without depth test
+    /// nor multisampling.
+    Composite,
+}
+
+impl<'a, V: Vertex> PipelineState<'a, V> {
+    pub fn with_mode(self, mode: RenderMode) -> Self {
+        Self { mode, ..self }
+    }
+}
+
 fn triangle_primitive() -> PrimitiveState {
     PrimitiveState {
         topology: PrimitiveTopology::TriangleList,
@@ -40,9 +70,33 @@ pub fn resampling<'a>() -> PipelineState<'a, ImageVertex> {
     PipelineState {
         name: "Resampling",
         shader_code: include_str!("shaders/resampling.wgsl"),
-        bindings: vec!["Source"],
+        bindings: vec!["Source", "Transform"],
+        primitive: triangle_primitive(),
+        mode: RenderMode::Opaque,
+        _vertex_type: PhantomData,
+    }
+}
+
+/// Draws the RGBA density map over the slice.
+pub fn overlay<'a>() -> PipelineState<'a, ImageVertex> {
+    PipelineState {
+        name: "Overlay",
+        mode: RenderMode::Overlay,
+        ..resampling()
+    }
+}
+
+/// Draws a lit tensor glyph at each voxel of the slice.
+pub fn glyph<'a>() -> PipelineState<'a, GlyphVertex> {
+    PipelineState {
+        name: "Glyph",
+        shader_code: concat!(
+            include_str!("shaders/uniforms.wgsl"),
+            include_str!("shaders/glyph.wgsl")
+        ),
+        bindings: vec!["Transform", "Lighting"],
         primitive: triangle_primitive(),
-        use_depth_test: false,
+        mode: RenderMode::Opaque,
         _vertex_type: PhantomData,
     }
 }
@@ -50,10 +104,89 @@ pub fn resampling<'a>() -> PipelineState<'a, ImageVertex> {
 pub fn streamline<'a>() -> PipelineState<'a, FiberVertex> {
     PipelineState {
         name: "Streamline",
-        shader_code: include_str!("shaders/streamline.wgsl"),
-        bindings: vec!["Transform"],
+        shader_code: concat!(
+            include_str!("shaders/uniforms.wgsl"),
+            include_str!("shaders/streamline.wgsl"),
+            include_str!("shaders/oit.wgsl")
+        ),
+        bindings: vec!["Transform", "Slab"],
         primitive: line_primitive(),
-        use_depth_test: true,
+        mode: RenderMode::Opaque,
+        _vertex_type: PhantomData,
+    }
+}
+
+/// Draws the peaks as line segments, like the streamlines.
+pub fn peaks<'a>() -> PipelineState<'a, FiberVertex> {
+    PipelineState {
+        name: "Peaks",
+        ..streamline()
+    }
+}
+
+/// Draws each segment as a screen-space quad, to get lines thicker than a pixel.
+pub fn ribbon<'a>() -> PipelineState<'a, SegmentVertex> {
+    PipelineState {
+        name: "Ribbon",
+        shader_code: concat!(
+            include_str!("shaders/uniforms.wgsl"),
+            include_str!("shaders/ribbon.wgsl"),
+            include_str!("shaders/oit.wgsl")
+        ),
+        bindings: vec!["Transform", "Slab", "Ribbon"],
+        primitive: triangle_primitive(),
+        mode: RenderMode::Opaque,
+        _vertex_type: PhantomData,
+    }
+}
+
+/// Draws each endpoint as a screen-space disk.
+pub fn sprite<'a>() -> PipelineState<'a, SpriteVertex> {
+    PipelineState {
+        name: "Sprite",
+        shader_code: concat!(
+            include_str!("shaders/uniforms.wgsl"),
+            include_str!("shaders/sprite.wgsl"),
+            include_str!("shaders/oit.wgsl")
+        ),
+        bindings: vec!["Transform", "Slab", "Ribbon"],
+        primitive: triangle_primitive(),
+        mode: RenderMode::Opaque,
+        _vertex_type: PhantomData,
+    }
+}
+
+pub fn tube<'a>() -> PipelineState<'a, TubeVertex> {
+    PipelineState {
+        name: "Tube",
+        shader_code: concat!(
+            include_str!("shaders/uniforms.wgsl"),
+            include_str!("shaders/tube.wgsl"),
+            include_str!("shaders/oit.wgsl")
+        ),
+        bindings: vec!["Transform", "Slab", "Lighting"],
+        primitive: triangle_primitive(),
+        mode: RenderMode::Opaque,
+        _vertex_type: PhantomData,
+    }
+}
+
+/// Draws the ODF glyphs as lit meshes, like the tubes.
+pub fn odf<'a>() -> PipelineState<'a, TubeVertex> {
+    PipelineState {
+        name: "Odf",
+        ..tube()
+    }
+}
+
+/// Draws the weighted blended transparency over the image.
+pub fn composite<'a>() -> PipelineState<'a, ImageVertex> {
+    PipelineState {
+        name: "Composite",
+        shader_code: include_str!("shaders/composite.wgsl"),
+        bindings: vec!["Composite"],
+        primitive: triangle_primitive(),
+        mode: RenderMode::Composite,
         _vertex_type: PhantomData,
     }
 }
@@ -1,16 +1,32 @@
 use std::collections::HashMap;
 
-use glam::{vec2, Mat3, Mat4};
-use trk_io::Reader;
+use bytemuck::Zeroable;
+use glam::{vec2, Mat4, Vec3};
+use nalgebra::Point3;
 use wgpu::{BindGroupLayout, Buffer};
 
-use crate::graphics::Client;
-use fibers::FiberBatch;
+use crate::{
+    graphics::{client::Blending, Client},
+    slicer::Slice,
+    ContextInputs,
+};
+use uniform::{Lighting, Ribbon, Slab};
 use vertex::ImageVertex;
 
 pub use {
-    coloring::Coloring,
-    texture::{Texture, COLOR_FORMAT, DEPTH_FORMAT},
+    coloring::{Coloring, DirectionEncoding},
+    colormap::Colormap,
+    density::{Density, DensityMap, DensityWeighting},
+    fibers::{Arrows, Clustering, EndpointColoring, EndpointSprites, FiberBatch, Tube},
+    filter::{Filter, Roi},
+    measure::Measure,
+    odf::{OdfField, ShBasis},
+    peaks::PeakField,
+    points::PointCloud,
+    subsampling::{Subsampling, SubsamplingMethod},
+    tensor::{GlyphShape, TensorField, TensorOrder, GLYPH_VERTEX_COUNT},
+    texture::{Texture, ACCUMULATION_FORMAT, COLOR_FORMAT, DEPTH_FORMAT, REVEALAGE_FORMAT},
+    uniform::Light,
 };
 
 pub mod bind {
@@ -19,8 +35,18 @@ pub mod bind {
 }
 mod buffer;
 mod coloring;
+mod colormap;
+mod density;
 mod fibers;
+mod filter;
+mod measure;
+mod odf;
+mod peaks;
+mod points;
+mod subsampling;
+mod tensor;
 mod texture;
+pub mod uniform;
 pub mod vertex;
 
 type BindLayouts = HashMap<String, BindGroupLayout>;
@@ -31,56 +57,185 @@ pub struct Resources {
     pub multisampled_texture: Texture,
     pub depth_texture: Texture,
     pub target_texture: Texture,
+    /// Only used by the weighted blended transparency
+    pub transparency_targets: Option<TransparencyTargets>,
 
     pub image_vertices: Buffer,
+    /// Quad covering the whole output image, in clip space
+    pub fullscreen_vertices: Buffer,
 
     pub transfer_buffer: Buffer,
     pub fibers: Vec<FiberBatch>,
+    /// Drawn instead of the fibers
+    pub density_map: Option<DensityMap>,
+    pub tensor_field: Option<TensorField>,
+    pub peak_field: Option<PeakField>,
+    pub odf_field: Option<OdfField>,
+    /// Instances of the sprite pipeline, and their number
+    pub point_sprites: Option<(Buffer, u32)>,
 
     pub transform: Buffer,
+    pub slab: Buffer,
+    pub ribbon: Buffer,
+    pub lighting: Buffer,
+}
+
+/// Render targets of the transparent fibers, composited over the target texture.
+pub struct TransparencyTargets {
+    pub multisampled_accumulation: Texture,
+    pub multisampled_revealage: Texture,
+    pub accumulation: Texture,
+    pub revealage: Texture,
+}
+
+impl TransparencyTargets {
+    fn new(client: &Client) -> Self {
+        let new = |name, format, multisampled| {
+            Texture::new_transparency(name, format, multisampled, client)
+        };
+        Self {
+            multisampled_accumulation: new("MultisampledAccumulation", ACCUMULATION_FORMAT, true),
+            multisampled_revealage: new("MultisampledRevealage", REVEALAGE_FORMAT, true),
+            accumulation: new("Accumulation", ACCUMULATION_FORMAT, false),
+            revealage: new("Revealage", REVEALAGE_FORMAT, false),
+        }
+    }
 }
 
 impl Resources {
-    pub fn new(fibers: Option<Reader>, client: &Client) -> Self {
+    /// Takes the data of the inputs, the settings are read from the client.
+    pub fn new(inputs: ContextInputs, client: &Client) -> Self {
+        let ContextInputs {
+            fibers_reader: fibers,
+            filter,
+            modulation,
+            tensor_field,
+            peak_field,
+            odf_field,
+            points,
+            ..
+        } = inputs;
         let device = &client.device;
         let target_texture = Texture::new_target(client);
 
-        let mut bind_layouts = vec![("Source".to_string(), bind::layout::source(device))];
-
+        let mut bind_layouts = vec![
+            ("Source".to_string(), bind::layout::source(device)),
+            ("Transform".to_string(), bind::layout::transform(device)),
+            ("Lighting".to_string(), bind::layout::lighting(device)),
+            ("Slab".to_string(), bind::layout::slab(device)),
+        ];
+
+        let (fibers, density_map) = match (fibers, &client.density) {
+            (Some(fibers), Some(density)) => {
+                let density_map = DensityMap::new(fibers, &filter, density, client);
+                (None, Some(density_map))
+            }
+            (fibers, _) => (fibers, None),
+        };
+        if fibers.is_some() || points.is_some() {
+            bind_layouts.push(("Ribbon".to_string(), bind::layout::ribbon(device)));
+        }
         let fibers = if let Some(fibers) = fibers {
-            bind_layouts.push(("Transform".to_string(), bind::layout::transform(device)));
-            fibers::batches(fibers, client)
+            fibers::batches(fibers, &filter, modulation.as_ref(), client)
         } else {
             vec![]
         };
+        let weighted = matches!(client.transparency(), Some(Blending::Weighted));
+        let transparency_targets = (!fibers.is_empty() && weighted).then(|| {
+            bind_layouts.push(("Composite".to_string(), bind::layout::composite(device)));
+            TransparencyTargets::new(client)
+        });
         Self {
             bind_layouts: bind_layouts.into_iter().collect(),
 
             image_vertices: buffer::init_image_vertex_buffer(device),
+            fullscreen_vertices: buffer::init_vertices(
+                "Fullscreen",
+                &fullscreen_vertices(),
+                device,
+            ),
 
             transfer_buffer: buffer::create_transfer_buffer(&target_texture, device),
             fibers,
+            density_map,
+            tensor_field,
+            peak_field,
+            odf_field,
+            point_sprites: points.map(|points| {
+                let vertices = points.vertices();
+                let buffer = buffer::init_vertices("Points", &vertices, device);
+                (buffer, vertices.len() as u32)
+            }),
 
             multisampled_texture: Texture::new_multisampled(client),
             depth_texture: Texture::new_depth(client),
             target_texture,
+            transparency_targets,
 
-            transform: buffer::create_transform(Mat4::IDENTITY, device),
+            transform: buffer::create_uniform("Transform", Mat4::IDENTITY, device),
+            slab: buffer::create_uniform("Slab", Slab::zeroed(), device),
+            ribbon: buffer::create_uniform("Ribbon", Ribbon::new(client), device),
+            lighting: buffer::create_uniform("Lighting", Lighting::zeroed(), device),
         }
     }
 }
 
-pub fn quad_vertices(transform: Mat3) -> [ImageVertex; 6] {
-    let vertex = |x, y, u, v| ImageVertex {
-        canon: transform.transform_point2(vec2(x, y)),
-        uv: vec2(u, v),
+/// Slices drawn in the same scene, like the three orthogonal planes through a crosshair.
+pub const MAX_PLANES: usize = 3;
+
+/// Positions the slice in the voxel space of the volume, at the center of its voxels.
+pub fn quad_vertices(slice: &Slice) -> [ImageVertex; 6] {
+    let axis = slice.view.axis();
+    let (u_axis, v_axis) = axis.in_plane();
+    let size = slice.size().as_vec2();
+
+    let vertex = |u, v| {
+        let mut position = Vec3::splat(slice.index as f32 + 0.5);
+        position[u_axis] = u * size.x;
+        position[v_axis] = v * size.y;
+
+        ImageVertex {
+            position,
+            uv: vec2(u, v),
+        }
+    };
+    [
+        vertex(0., 0.),
+        vertex(1., 0.),
+        vertex(1., 1.),
+        vertex(0., 0.),
+        vertex(1., 1.),
+        vertex(0., 1.),
+    ]
+}
+
+/// Voxels of the slice, with their `(u, v)` indices in the plane and their centers in voxel
+/// space. Used to draw a glyph per voxel.
+pub fn slice_voxels(slice: &Slice) -> impl Iterator<Item = ((usize, usize), Point3<f32>)> {
+    let (u_axis, v_axis) = slice.view.axis().in_plane();
+    let (size, index) = (slice.size(), slice.index);
+
+    (0..size.x as usize).flat_map(move |u| {
+        (0..size.y as usize).map(move |v| {
+            let mut center = Point3::from([index as f32 + 0.5; 3]);
+            center[u_axis] = u as f32 + 0.5;
+            center[v_axis] = v as f32 + 0.5;
+            ((u, v), center)
+        })
+    })
+}
+
+fn fullscreen_vertices() -> [ImageVertex; 6] {
+    let vertex = |x, y| ImageVertex {
+        position: Vec3::new(x, y, 0.),
+        uv: vec2((x + 1.) / 2., (1. - y) / 2.),
     };
     [
-        vertex(1., 1., 0., 1.),
-        vertex(1., -1., 0., 0.),
-        vertex(-1., -1., 1., 0.),
-        vertex(1., 1., 0., 1.),
-        vertex(-1., -1., 1., 0.),
-        vertex(-1., 1., 1., 1.),
+        vertex(-1., -1.),
+        vertex(1., -1.),
+        vertex(1., 1.),
+        vertex(-1., -1.),
+        vertex(1., 1.),
+        vertex(-1., 1.),
     ]
 }
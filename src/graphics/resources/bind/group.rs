@@ -2,12 +2,12 @@ use wgpu::{AddressMode, BindGroup, BindGroupEntry, BindingResource, FilterMode};
 
 use crate::{
     graphics::{resources::Texture, Client, Context},
-    ImageSlice,
+    slicer::Slice,
 };
 
-pub fn source(image: &ImageSlice, ctx: &Context) -> BindGroup {
+pub fn source(slice: &Slice, ctx: &Context) -> BindGroup {
     let sampler = create_sampler(&ctx.client);
-    let source_texture = Texture::new_source(image, &ctx.client);
+    let source_texture = Texture::new_source(&slice.data, slice.size(), &ctx.client);
 
     let entries = vec![
         BindingResource::TextureView(&source_texture.view),
@@ -16,11 +16,64 @@ pub fn source(image: &ImageSlice, ctx: &Context) -> BindGroup {
     create_bind_group("Source", entries, ctx)
 }
 
+/// Density map of the slice, using the source layout.
+pub fn overlay(slice: &Slice, ctx: &Context) -> BindGroup {
+    let density_map = ctx
+        .res
+        .density_map
+        .as_ref()
+        .expect("Overlay requires a density map");
+    let density = ctx
+        .client
+        .density
+        .as_ref()
+        .expect("Set with the density map");
+
+    let sampler = create_sampler(&ctx.client);
+    let (bytes, size) = density_map.slice_rgba(slice, &density.colormap, ctx.client.opacity);
+    let overlay_texture = Texture::new_overlay(&bytes, size, &ctx.client);
+
+    let entries = vec![
+        BindingResource::TextureView(&overlay_texture.view),
+        BindingResource::Sampler(&sampler),
+    ];
+    create_bind_group("Source", entries, ctx)
+}
+
 pub fn transform(ctx: &Context) -> BindGroup {
     let entries = vec![ctx.res.transform.as_entire_binding()];
     create_bind_group("Transform", entries, ctx)
 }
 
+pub fn slab(ctx: &Context) -> BindGroup {
+    let entries = vec![ctx.res.slab.as_entire_binding()];
+    create_bind_group("Slab", entries, ctx)
+}
+
+pub fn ribbon(ctx: &Context) -> BindGroup {
+    let entries = vec![ctx.res.ribbon.as_entire_binding()];
+    create_bind_group("Ribbon", entries, ctx)
+}
+
+pub fn lighting(ctx: &Context) -> BindGroup {
+    let entries = vec![ctx.res.lighting.as_entire_binding()];
+    create_bind_group("Lighting", entries, ctx)
+}
+
+pub fn composite(ctx: &Context) -> BindGroup {
+    let targets = ctx
+        .res
+        .transparency_targets
+        .as_ref()
+        .expect("Composite requires the transparency targets");
+
+    let entries = vec![
+        BindingResource::TextureView(&targets.accumulation.view),
+        BindingResource::TextureView(&targets.revealage.view),
+    ];
+    create_bind_group("Composite", entries, ctx)
+}
+
 fn create_sampler(client: &Client) -> wgpu::Sampler {
     client.device.create_sampler(&wgpu::SamplerDescriptor {
         address_mode_u: AddressMode::ClampToEdge,
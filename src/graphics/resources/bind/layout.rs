@@ -27,15 +27,48 @@ pub fn source(device: &Device) -> BindGroupLayout {
 }
 
 pub fn transform(device: &Device) -> BindGroupLayout {
-    let entries = vec![LayoutEntry {
-        stage: ShaderStages::VERTEX,
+    create_layout("Transform", &[uniform(ShaderStages::VERTEX)], device)
+}
+
+pub fn slab(device: &Device) -> BindGroupLayout {
+    create_layout("Slab", &[uniform(ShaderStages::FRAGMENT)], device)
+}
+
+pub fn ribbon(device: &Device) -> BindGroupLayout {
+    create_layout("Ribbon", &[uniform(ShaderStages::VERTEX_FRAGMENT)], device)
+}
+
+pub fn lighting(device: &Device) -> BindGroupLayout {
+    create_layout(
+        "Lighting",
+        &[uniform(ShaderStages::VERTEX_FRAGMENT)],
+        device,
+    )
+}
+
+pub fn composite(device: &Device) -> BindGroupLayout {
+    let texture = || LayoutEntry {
+        stage: ShaderStages::FRAGMENT,
+        binding_type: BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+    };
+    // Accumulation and revealage
+    let entries = vec![texture(), texture()];
+    create_layout("Composite", &entries, device)
+}
+
+fn uniform(stage: ShaderStages) -> LayoutEntry {
+    LayoutEntry {
+        stage,
         binding_type: BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
             has_dynamic_offset: false,
             min_binding_size: None,
         },
-    }];
-    create_layout("Transform", &entries, device)
+    }
 }
 
 fn create_layout(name: &str, entries: &[LayoutEntry], device: &Device) -> BindGroupLayout {
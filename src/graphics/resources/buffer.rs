@@ -1,16 +1,15 @@
-use bytemuck::Pod;
-use glam::{Mat3, Mat4};
+use bytemuck::{Pod, Zeroable};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
     Buffer, BufferUsages, Device,
 };
 
-use crate::graphics::resources::{quad_vertices, Texture};
+use crate::graphics::resources::{vertex::ImageVertex, Texture, MAX_PLANES};
 
 pub fn init_image_vertex_buffer(device: &Device) -> Buffer {
     device.create_buffer_init(&BufferInitDescriptor {
         label: label!("ImageVertexBuffer"),
-        contents: bytemuck::cast_slice(&quad_vertices(Mat3::ZERO)),
+        contents: bytemuck::cast_slice(&[ImageVertex::zeroed(); 6 * MAX_PLANES]),
         usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
     })
 }
@@ -40,10 +39,10 @@ pub fn create_transfer_buffer(texture: &Texture, device: &Device) -> Buffer {
     })
 }
 
-pub fn create_transform(transform: Mat4, device: &Device) -> Buffer {
+pub fn create_uniform<T: Pod>(name: &str, data: T, device: &Device) -> Buffer {
     device.create_buffer_init(&BufferInitDescriptor {
-        label: label!("TransformUniformBuffer"),
-        contents: bytemuck::cast_slice(&[transform]),
+        label: label!("{name}UniformBuffer"),
+        contents: bytemuck::cast_slice(&[data]),
         usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
     })
 }
@@ -1,41 +1,110 @@
 use std::ops::Range;
 
-use nalgebra::Vector3;
+use nalgebra::{Matrix3, Vector3};
+use ndarray::Array3;
 
-use super::vertex::FiberVertex;
+use super::{
+    colormap::{self, Colormap, Limits},
+    fibers::{Clustering, Streamline},
+    measure::Measure,
+    vertex::FiberVertex,
+};
 
 #[derive(Clone, Copy)]
 pub enum Coloring {
-    Local,
-    Endpoint,
+    Local(DirectionEncoding),
+    Endpoint(DirectionEncoding),
     Uniform(Vector3<u32>),
+    /// Maps a value of each vertex through a colormap. Limits are computed from the
+    /// tractogram when they are not provided.
+    Mapped(Measure, Colormap, Option<Limits>),
+    /// One categorical color per QuickBundles cluster
+    Cluster(Clustering),
+}
+
+/// Converts directions to the usual red (left-right), green (anterior-posterior) and
+/// blue (superior-inferior) colors.
+#[derive(Clone, Copy)]
+pub struct DirectionEncoding {
+    /// Linear part of the image affine, so that the directions are expressed in RAS+ mm
+    pub voxel_to_mm: Matrix3<f32>,
+    /// 0 is grayscale, 1 leaves the colors unchanged and larger values saturate them
+    pub saturation: f32,
+    /// Values above 1 brighten the colors, values below 1 darken them
+    pub gamma: f32,
+}
+
+impl DirectionEncoding {
+    /// `direction` is in voxel space and doesn't need to be normalized.
+    pub fn color(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        let color = (self.voxel_to_mm * direction).normalize().abs();
+
+        let gray = Vector3::repeat(color.mean());
+        let color = gray + (color - gray) * self.saturation;
+
+        color.map(|c| c.clamp(0., 1.).powf(1. / self.gamma))
+    }
 }
 
 impl Coloring {
-    pub fn assign_vertex_colors(&self, vertices: &mut [FiberVertex], ranges: &[Range<usize>]) {
+    /// Returns one value per point of the streamline.
+    pub fn vertex_values(&self, streamline: &Streamline, voxel_to_mm: &Matrix3<f32>) -> Vec<f32> {
         match self {
-            Coloring::Local => Self::assign_local(vertices, ranges),
-            Coloring::Endpoint => Self::assign_endpoint(vertices, ranges),
+            Coloring::Mapped(measure, _, _) => measure.values(streamline, voxel_to_mm),
+            _ => vec![],
+        }
+    }
+
+    /// Computes the missing limits from all vertex values of the tractogram.
+    pub fn fit_limits<'a>(&self, values: impl Iterator<Item = &'a f32>) -> Self {
+        match *self {
+            Coloring::Mapped(measure, colormap, None) => {
+                Coloring::Mapped(measure, colormap, Some(colormap::limits(values)))
+            }
+            coloring => coloring,
+        }
+    }
+
+    pub fn assign_vertex_colors(
+        &self,
+        vertices: &mut [FiberVertex],
+        ranges: &[Range<usize>],
+        values: &[f32],
+    ) {
+        match self {
+            Coloring::Local(encoding) => Self::assign_local(vertices, ranges, encoding),
+            Coloring::Endpoint(encoding) => Self::assign_endpoint(vertices, ranges, encoding),
             Coloring::Uniform(color) => Self::assign_uniform(vertices, *color),
+            Coloring::Mapped(_, colormap, limits) => {
+                let limits = limits.expect("Limits are fitted before assigning the colors");
+                Self::assign_mapped(vertices, values, colormap, limits)
+            }
+            Coloring::Cluster(_) => Self::assign_cluster(vertices, values),
         }
     }
 
-    fn assign_local(vertices: &mut [FiberVertex], ranges: &[Range<usize>]) {
+    fn assign_local(
+        vertices: &mut [FiberVertex],
+        ranges: &[Range<usize>],
+        encoding: &DirectionEncoding,
+    ) {
         for range in ranges {
             for i in range.start..range.end {
-                vertices[i].color = (vertices[i + 1].position - vertices[i].position)
-                    .normalize()
-                    .abs();
+                vertices[i].color = encoding.color(vertices[i + 1].position - vertices[i].position);
             }
             // Last point of the streamline uses the previous color.
             vertices[range.end].color = vertices[range.end - 1].color;
         }
     }
 
-    fn assign_endpoint(vertices: &mut [FiberVertex], ranges: &[Range<usize>]) {
+    fn assign_endpoint(
+        vertices: &mut [FiberVertex],
+        ranges: &[Range<usize>],
+        encoding: &DirectionEncoding,
+    ) {
         for range in ranges {
             let (first, last) = (&vertices[range.start], &vertices[range.end]);
-            let color = (first.position - last.position).normalize().abs();
+            let color = encoding.color(first.position - last.position);
 
             for vertex in &mut vertices[range.start..=range.end] {
                 vertex.color = color
@@ -50,4 +119,34 @@ impl Coloring {
             vertex.color = color;
         }
     }
+
+    fn assign_mapped(
+        vertices: &mut [FiberVertex],
+        values: &[f32],
+        colormap: &Colormap,
+        limits: Limits,
+    ) {
+        for (vertex, &value) in vertices.iter_mut().zip(values) {
+            vertex.color = colormap.color(value, limits);
+        }
+    }
+
+    /// `clusters` are the cluster of each vertex.
+    fn assign_cluster(vertices: &mut [FiberVertex], clusters: &[f32]) {
+        for (vertex, &cluster) in vertices.iter_mut().zip(clusters) {
+            vertex.color = colormap::category(cluster as i64);
+        }
+    }
+}
+
+/// Multiplies the colors by the value of `map` in the voxel of each vertex. The values are
+/// expected to be in `[0, 1]`, like a FA map.
+pub fn modulate(vertices: &mut [FiberVertex], map: &Array3<f32>) {
+    let (x, y, z) = map.dim();
+
+    for vertex in vertices {
+        let p = vertex.position.map(|c| c.max(0.) as usize);
+        let value = map[(p.x.min(x - 1), p.y.min(y - 1), p.z.min(z - 1))];
+        vertex.color *= value.clamp(0., 1.);
+    }
 }
@@ -0,0 +1,89 @@
+use nalgebra::Vector3;
+
+/// Values mapped to the first and last colors of a colormap.
+pub type Limits = (f32, f32);
+
+type Rgb = [f32; 3];
+
+const GRAY: [Rgb; 2] = [[0., 0., 0.], [1., 1., 1.]];
+
+const HOT: [Rgb; 4] = [[0., 0., 0.], [1., 0., 0.], [1., 1., 0.], [1., 1., 1.]];
+
+const VIRIDIS: [Rgb; 11] = [
+    [0.267, 0.005, 0.329],
+    [0.282, 0.141, 0.459],
+    [0.255, 0.267, 0.529],
+    [0.208, 0.373, 0.553],
+    [0.165, 0.471, 0.557],
+    [0.129, 0.569, 0.549],
+    [0.133, 0.659, 0.518],
+    [0.267, 0.749, 0.439],
+    [0.478, 0.820, 0.3176],
+    [0.741, 0.875, 0.149],
+    [0.992, 0.906, 0.145],
+];
+
+/// Matplotlib's `tab10` palette.
+const CATEGORIES: [Rgb; 10] = [
+    [0.122, 0.467, 0.706],
+    [1.000, 0.498, 0.055],
+    [0.173, 0.627, 0.173],
+    [0.839, 0.153, 0.157],
+    [0.580, 0.404, 0.741],
+    [0.549, 0.337, 0.294],
+    [0.890, 0.467, 0.761],
+    [0.498, 0.498, 0.498],
+    [0.737, 0.741, 0.133],
+    [0.090, 0.745, 0.812],
+];
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Colormap {
+    Gray,
+    Hot,
+    Viridis,
+    /// One distinct color per integer value, e.g. a cluster id. Limits are ignored.
+    Categorical,
+}
+
+impl Colormap {
+    pub fn color(&self, value: f32, limits: Limits) -> Vector3<f32> {
+        let t = normalize(value, limits);
+        match self {
+            Colormap::Gray => interpolate(&GRAY, t),
+            Colormap::Hot => interpolate(&HOT, t),
+            Colormap::Viridis => interpolate(&VIRIDIS, t),
+            Colormap::Categorical => category(value.round() as i64),
+        }
+    }
+}
+
+/// Returns the smallest and largest values.
+pub fn limits<'a>(values: impl Iterator<Item = &'a f32>) -> Limits {
+    values.fold((f32::MAX, f32::MIN), |(min, max), &v| {
+        (min.min(v), max.max(v))
+    })
+}
+
+/// Returns the color at `index` in a palette of distinct colors, wrapping around.
+pub fn category(index: i64) -> Vector3<f32> {
+    let len = CATEGORIES.len() as i64;
+    CATEGORIES[index.rem_euclid(len) as usize].into()
+}
+
+fn normalize(value: f32, (min, max): Limits) -> f32 {
+    if max > min {
+        ((value - min) / (max - min)).clamp(0., 1.)
+    } else {
+        0.
+    }
+}
+
+/// Linear interpolation between evenly spaced colors, `t` being in `[0, 1]`.
+fn interpolate(colors: &[Rgb], t: f32) -> Vector3<f32> {
+    let position = t * (colors.len() - 1) as f32;
+    let i = (position as usize).min(colors.len() - 2);
+    let (a, b) = (Vector3::from(colors[i]), Vector3::from(colors[i + 1]));
+
+    a.lerp(&b, position - i as f32)
+}
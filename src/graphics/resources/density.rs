@@ -0,0 +1,115 @@
+use glam::{uvec2, UVec2, UVec3};
+use nalgebra::{Matrix3, Point3};
+use ndarray::{Array3, Axis};
+use trk_io::Reader;
+
+use super::{
+    colormap::{self, Colormap, Limits},
+    fibers,
+    filter::Filter,
+    Client,
+};
+use crate::slicer::Slice;
+
+/// Track density imaging, drawn over the slices instead of the fibers.
+#[derive(Clone, Copy)]
+pub struct Density {
+    pub weighting: DensityWeighting,
+    pub colormap: Colormap,
+    /// Defaults to the range of the map
+    pub limits: Option<Limits>,
+    /// Dimensions of the image grid
+    pub size_3d: UVec3,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum DensityWeighting {
+    /// Number of streamlines crossing each voxel
+    Counts,
+    /// Length, in mm, of the streamlines within each voxel
+    Length,
+}
+
+/// Density of the streamlines in each voxel of the image grid.
+pub struct DensityMap {
+    values: Array3<f32>,
+    limits: Limits,
+}
+
+impl DensityMap {
+    pub fn new(fibers: Reader, filter: &Filter, density: &Density, client: &Client) -> Self {
+        let size = density.size_3d;
+        let mut values = Array3::zeros((size.x as usize, size.y as usize, size.z as usize));
+
+        for streamline in fibers::kept_streamlines(fibers, filter, client) {
+            let mut visited = vec![];
+            for segment in streamline.points.windows(2) {
+                for (voxel, length) in voxelize(&segment[0], &segment[1], &client.voxel_to_mm) {
+                    let Some(value) = values.get_mut(voxel) else {
+                        continue; // Outside the image
+                    };
+                    match density.weighting {
+                        DensityWeighting::Counts => visited.push(voxel),
+                        DensityWeighting::Length => *value += length,
+                    }
+                }
+            }
+            // Each streamline is counted once per voxel
+            visited.sort_unstable();
+            visited.dedup();
+            for voxel in visited {
+                values[voxel] += 1.;
+            }
+        }
+
+        let limits = density
+            .limits
+            .unwrap_or_else(|| (0., colormap::limits(values.iter()).1));
+        Self { values, limits }
+    }
+
+    /// Colormapped density of the slice, as RGBA bytes. The empty voxels are transparent.
+    pub fn slice_rgba(&self, slice: &Slice, colormap: &Colormap, opacity: f32) -> (Vec<u8>, UVec2) {
+        let axis = slice.view.axis();
+        let values = self.values.index_axis(Axis(axis as usize), slice.index);
+        let (width, height) = values.dim();
+
+        let alpha = (opacity * 255.).round() as u8;
+        let mut bytes = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let value = values[(x, y)];
+                if value > 0. {
+                    let color = colormap.color(value, self.limits);
+                    bytes.extend(color.iter().map(|c| (c * 255.).round() as u8));
+                    bytes.push(alpha);
+                } else {
+                    bytes.extend([0; 4]);
+                }
+            }
+        }
+        (bytes, uvec2(width as u32, height as u32))
+    }
+}
+
+/// Splits the segment in pieces smaller than half a voxel. Returns the voxel of each piece,
+/// and its length in mm.
+fn voxelize(
+    start: &Point3<f32>,
+    end: &Point3<f32>,
+    voxel_to_mm: &Matrix3<f32>,
+) -> impl Iterator<Item = ((usize, usize, usize), f32)> {
+    let direction = end - start;
+    let nb_pieces = (direction.norm() * 2.).ceil().max(1.) as usize;
+    let piece_length = (voxel_to_mm * direction).norm() / nb_pieces as f32;
+    let start = *start;
+
+    (0..nb_pieces).filter_map(move |i| {
+        let middle = start + direction * ((i as f32 + 0.5) / nb_pieces as f32);
+        if middle.iter().any(|&c| c < 0.) {
+            return None;
+        }
+        let voxel = (middle.x as usize, middle.y as usize, middle.z as usize);
+        Some((voxel, piece_length))
+    })
+}
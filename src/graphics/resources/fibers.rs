@@ -1,71 +1,403 @@
 use std::ops::Range;
 
-use nalgebra::Vector3;
-use trk_io::{Point, Reader};
+use bytemuck::Pod;
+use nalgebra::{Matrix3, Point3, Vector3};
+use ndarray::Array3;
+use trk_io::{Points, Reader, TractogramItem};
 use wgpu::Buffer;
 
-use super::{buffer, vertex::FiberVertex, Client, Coloring};
+use super::{
+    buffer, coloring, colormap,
+    filter::Filter,
+    vertex::{FiberVertex, SegmentVertex, SpriteVertex, TubeVertex},
+    Client, Coloring,
+};
+use crate::graphics::client::FiberStyle;
+use quickbundles::QuickBundles;
 
-type Streamline = Vec<Point>;
+pub use quickbundles::Clustering;
 
-pub struct FiberBatch {
-    pub vertices: Buffer,
-    pub indices: Buffer,
-    pub index_count: u32,
+mod quickbundles;
+
+/// Points of a streamline, with the per-point scalars and the properties of the TrackVis file.
+pub struct Streamline {
+    pub points: Points,
+    /// Interleaved scalars, `points.len() * nb_scalars` values
+    pub scalars: Vec<f32>,
+    pub properties: Vec<f32>,
 }
 
-impl FiberBatch {
-    fn new(streamlines: Vec<Streamline>, client: &Client) -> Self {
-        let (vertices, indices) = geometry(streamlines, &client.coloring);
+impl Streamline {
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
 
-        let name = "Fiber";
+    /// Returns the scalar at `index` of each point, nothing when the file has no scalars.
+    pub fn scalar(&self, index: usize) -> impl Iterator<Item = f32> + '_ {
+        let nb_scalars = self.scalars.len().checked_div(self.len()).unwrap_or(0);
+        let scalars = if nb_scalars > 0 {
+            &self.scalars[..]
+        } else {
+            &[]
+        };
+        scalars
+            .iter()
+            .skip(index)
+            .step_by(nb_scalars.max(1))
+            .copied()
+    }
+}
 
+impl From<TractogramItem> for Streamline {
+    fn from((points, scalars, properties): TractogramItem) -> Self {
         Self {
-            vertices: buffer::init_vertices(name, &vertices, &client.device),
-            indices: buffer::init_indices(name, &indices, &client.device),
+            points,
+            scalars: scalars.data,
+            properties,
+        }
+    }
+}
+
+/// Geometry of the fibers drawn as tubes.
+#[derive(Clone, Copy)]
+pub struct Tube {
+    /// In mm
+    pub radius: f32,
+    /// Number of vertices around each point
+    pub sides: u32,
+}
+
+/// Arrowheads along the fibers, pointing toward their last point.
+#[derive(Clone, Copy)]
+pub struct Arrows {
+    /// Arc length between two arrowheads, in mm, positive
+    pub spacing: f32,
+    /// Length of the arrowheads, in mm
+    pub size: f32,
+}
+
+/// Disks drawn at both ends of the fibers, instead of the fibers.
+#[derive(Clone, Copy)]
+pub struct EndpointSprites {
+    /// Diameter, in pixels
+    pub size: f32,
+    pub coloring: EndpointColoring,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum EndpointColoring {
+    /// One color for the first points and another one for the last points
+    End,
+    /// Color of the streamline, as given by the coloring mode
+    Streamline,
+}
+
+/// CPU side of a batch, kept until the coloring limits or the clusters are known.
+struct Geometry {
+    vertices: Vec<FiberVertex>,
+    ranges: Vec<Range<usize>>,
+    /// Used by the colormap-based coloring, empty otherwise
+    values: Vec<f32>,
+}
+
+pub enum FiberBatch {
+    /// Indexed vertices
+    Mesh {
+        vertices: Buffer,
+        indices: Buffer,
+        index_count: u32,
+    },
+    /// Instances expanded to quads in the vertex shader
+    Instances {
+        instances: Buffer,
+        instance_count: u32,
+    },
+}
+
+impl FiberBatch {
+    fn new(
+        mut geometry: Geometry,
+        coloring: &Coloring,
+        modulation: Option<&Array3<f32>>,
+        client: &Client,
+    ) -> Self {
+        coloring.assign_vertex_colors(&mut geometry.vertices, &geometry.ranges, &geometry.values);
+        if let Some(map) = modulation {
+            coloring::modulate(&mut geometry.vertices, map);
+        }
+        if let Some(arrows) = &client.arrows {
+            // The arrowheads would be drawn as sprites
+            if client.fiber_style() != FiberStyle::Endpoints {
+                add_arrows(
+                    &mut geometry,
+                    arrows,
+                    &client.voxel_to_mm,
+                    &client.mm_to_voxel,
+                );
+            }
+        }
+
+        match client.fiber_style() {
+            FiberStyle::Lines => {
+                let indices = indices(&geometry.ranges);
+                Self::mesh("Fiber", &geometry.vertices, &indices, client)
+            }
+            FiberStyle::Ribbons => {
+                let segments = segments(&geometry.vertices, &geometry.ranges);
+
+                Self::Instances {
+                    instances: buffer::init_vertices("Segment", &segments, &client.device),
+                    instance_count: segments.len() as u32,
+                }
+            }
+            FiberStyle::Tubes => {
+                let tube = client
+                    .tube
+                    .as_ref()
+                    .expect("Tubes style requires tube parameters");
+                let (vertices, indices) = tubes(
+                    &geometry.vertices,
+                    &geometry.ranges,
+                    tube,
+                    &client.voxel_to_mm,
+                    &client.mm_to_voxel,
+                );
+                Self::mesh("Tube", &vertices, &indices, client)
+            }
+            FiberStyle::Endpoints => {
+                let sprites_coloring = client
+                    .endpoint_sprites
+                    .expect("Endpoints style requires sprites parameters")
+                    .coloring;
+                let sprites = sprites(&geometry.vertices, &geometry.ranges, sprites_coloring);
+
+                Self::Instances {
+                    instances: buffer::init_vertices("Sprite", &sprites, &client.device),
+                    instance_count: sprites.len() as u32,
+                }
+            }
+        }
+    }
+
+    fn mesh<V: Pod>(name: &str, vertices: &[V], indices: &[u32], client: &Client) -> Self {
+        Self::Mesh {
+            vertices: buffer::init_vertices(name, vertices, &client.device),
+            indices: buffer::init_indices(name, indices, &client.device),
             index_count: indices.len() as u32,
         }
     }
 }
 
-pub fn batches(fibers: Reader, client: &Client) -> Vec<FiberBatch> {
-    let mut iter = fibers.into_streamlines_iter();
+/// Streamlines kept by the filter, then subsampled. The fibers and the density draw the same
+/// streamlines.
+pub fn kept_streamlines<'a>(
+    fibers: Reader,
+    filter: &'a Filter,
+    client: &'a Client,
+) -> impl Iterator<Item = Streamline> + 'a {
+    // The number of streamlines kept by the filter is unknown
+    let total = if filter.keeps_all() {
+        fibers.header.nb_streamlines
+    } else {
+        0
+    };
+    let streamlines = fibers
+        .map(Streamline::from)
+        .filter(|streamline| filter.keeps(streamline, &client.voxel_to_mm));
+    client.subsampling.apply(streamlines, total)
+}
+
+/// Only the streamlines kept by `filter` are drawn, and subsampled. `modulation` is an optional
+/// map, in the image grid, multiplying the colors.
+pub fn batches(
+    fibers: Reader,
+    filter: &Filter,
+    modulation: Option<&Array3<f32>>,
+    client: &Client,
+) -> Vec<FiberBatch> {
+    let mut iter = kept_streamlines(fibers, filter, client);
 
-    std::iter::from_fn(|| {
+    let geometries = std::iter::from_fn(|| {
         let streamlines: Vec<Streamline> =
             iter.by_ref().take(client.streamline_batch_size).collect();
 
-        (!streamlines.is_empty()).then(|| FiberBatch::new(streamlines, client))
-    })
-    .collect()
+        (!streamlines.is_empty()).then(|| geometry(streamlines, client))
+    });
+
+    // Each batch is uploaded as soon as it is read, unless the coloring depends on the whole
+    // tractogram
+    if !matches!(
+        client.coloring,
+        Coloring::Mapped(_, _, None) | Coloring::Cluster(_)
+    ) {
+        return geometries
+            .map(|geometry| FiberBatch::new(geometry, &client.coloring, modulation, client))
+            .collect();
+    }
+    let geometries: Vec<Geometry> = geometries.collect();
+
+    let geometries = match &client.coloring {
+        Coloring::Cluster(clustering) => cluster(
+            geometries,
+            clustering,
+            &client.voxel_to_mm,
+            &client.mm_to_voxel,
+        ),
+        _ => geometries,
+    };
+
+    let coloring = client
+        .coloring
+        .fit_limits(geometries.iter().flat_map(|geometry| &geometry.values));
+
+    geometries
+        .into_iter()
+        .map(|geometry| FiberBatch::new(geometry, &coloring, modulation, client))
+        .collect()
+}
+
+fn geometry(streamlines: Vec<Streamline>, client: &Client) -> Geometry {
+    // Computed on all the points, before the simplification
+    let values: Vec<f32> = streamlines
+        .iter()
+        .flat_map(|streamline| {
+            client
+                .coloring
+                .vertex_values(streamline, &client.voxel_to_mm)
+        })
+        .collect();
+    let (vertices, ranges, kept) = vertices(streamlines, client.simplify);
+    let values = if values.is_empty() {
+        values
+    } else {
+        kept.into_iter().map(|i| values[i]).collect()
+    };
+
+    Geometry {
+        vertices,
+        ranges,
+        values,
+    }
 }
 
-fn geometry(streamlines: Vec<Streamline>, coloring: &Coloring) -> (Vec<FiberVertex>, Vec<u32>) {
-    let (mut vertices, ranges) = vertices(streamlines);
-    coloring.assign_vertex_colors(&mut vertices, &ranges);
+/// Sets the cluster of each vertex as its value. When only the centroids are drawn, they
+/// replace the streamlines.
+fn cluster(
+    mut geometries: Vec<Geometry>,
+    clustering: &Clustering,
+    voxel_to_mm: &Matrix3<f32>,
+    mm_to_voxel: &Matrix3<f32>,
+) -> Vec<Geometry> {
+    let mut quickbundles = QuickBundles::new(clustering);
+
+    for geometry in &mut geometries {
+        geometry.values = vec![0.; geometry.vertices.len()];
+        for range in &geometry.ranges {
+            let streamline = range.start..=range.end;
+            let points: Vec<Point3<f32>> = geometry.vertices[streamline.clone()]
+                .iter()
+                .map(|v| (voxel_to_mm * v.position.coords).into())
+                .collect();
+
+            let cluster = quickbundles.assign(&points);
+            geometry.values[streamline].fill(cluster as f32);
+        }
+    }
+    if !clustering.centroids_only {
+        return geometries;
+    }
 
-    (vertices, indices(&ranges))
+    let (mut vertices, mut ranges, mut values) = (vec![], vec![], vec![]);
+    for (cluster, centroid) in quickbundles.centroids().enumerate() {
+        ranges.push(vertices.len()..vertices.len() + centroid.len() - 1);
+        vertices.extend(centroid.iter().map(|point| FiberVertex {
+            position: (mm_to_voxel * point.coords).into(),
+            color: Vector3::default(), // Calculated later
+        }));
+        values.resize(vertices.len(), cluster as f32);
+    }
+    vec![Geometry {
+        vertices,
+        ranges,
+        values,
+    }]
 }
 
-fn vertices(streamlines: Vec<Streamline>) -> (Vec<FiberVertex>, Vec<Range<usize>>) {
+/// Also returns the index, among all the points of `streamlines`, of each vertex. Points are
+/// removed when `tolerance`, in voxels, is provided.
+fn vertices(
+    streamlines: Vec<Streamline>,
+    tolerance: Option<f32>,
+) -> (Vec<FiberVertex>, Vec<Range<usize>>, Vec<usize>) {
     let mut delimiter = 0;
+    let mut offset = 0; // Index of the first point of the streamline
     let mut ranges = vec![]; // Streamlines ranges
+    let mut kept = vec![];
 
     let vertices: Vec<FiberVertex> = streamlines
         .into_iter()
         .flat_map(|streamline| {
+            let indices = match tolerance {
+                Some(tolerance) => simplify(&streamline.points, tolerance),
+                None => (0..streamline.len()).collect(),
+            };
             let start = delimiter;
-            ranges.push(start..start + streamline.len() - 1);
-            delimiter += streamline.len();
+            ranges.push(start..start + indices.len() - 1);
+            delimiter += indices.len();
+            kept.extend(indices.iter().map(|i| offset + i));
+            offset += streamline.len();
 
-            streamline.into_iter().map(|point| FiberVertex {
-                position: point,
+            indices.into_iter().map(move |i| FiberVertex {
+                position: streamline.points[i],
                 color: Vector3::default(), // Calculated later
             })
         })
         .collect();
 
-    (vertices, ranges)
+    (vertices, ranges, kept)
+}
+
+/// Ramer-Douglas-Peucker simplification. Returns the indices of the points to keep, which
+/// include both ends.
+fn simplify(points: &[Point3<f32>], tolerance: f32) -> Vec<usize> {
+    if points.len() <= 2 {
+        return (0..points.len()).collect();
+    }
+    let mut keep = vec![false; points.len()];
+    let last = points.len() - 1;
+    (keep[0], keep[last]) = (true, true);
+
+    // Iterative, the streamlines may have thousands of points
+    let mut spans = vec![(0, last)];
+    while let Some((first, last)) = spans.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(&points[i], &points[first], &points[last]),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                spans.extend([(first, i), (i, last)]);
+            }
+        }
+    }
+    (0..points.len()).filter(|&i| keep[i]).collect()
+}
+
+fn distance_to_segment(point: &Point3<f32>, start: &Point3<f32>, end: &Point3<f32>) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.norm_squared();
+    let t = if length_squared > f32::EPSILON {
+        ((point - start).dot(&segment) / length_squared).clamp(0., 1.)
+    } else {
+        0.
+    };
+    (point - (start + segment * t)).norm()
 }
 
 fn indices(ranges: &[Range<usize>]) -> Vec<u32> {
@@ -75,3 +407,172 @@ fn indices(ranges: &[Range<usize>]) -> Vec<u32> {
         .flat_map(|range| range.flat_map(|i| vec![i as u32, i as u32 + 1]))
         .collect()
 }
+
+fn segments(vertices: &[FiberVertex], ranges: &[Range<usize>]) -> Vec<SegmentVertex> {
+    ranges
+        .iter()
+        .cloned()
+        .flat_map(|range| {
+            range.map(|i| SegmentVertex {
+                start: vertices[i],
+                end: vertices[i + 1],
+            })
+        })
+        .collect()
+}
+
+/// Adds each arrowhead as four short streamlines, forming a 3D chevron visible from all views.
+/// They use the color of the streamline where they are placed.
+fn add_arrows(
+    geometry: &mut Geometry,
+    arrows: &Arrows,
+    voxel_to_mm: &Matrix3<f32>,
+    mm_to_voxel: &Matrix3<f32>,
+) {
+    for range in geometry.ranges.clone() {
+        let mut arc_length = 0.;
+        let mut next_arrow = arrows.spacing;
+
+        for i in range {
+            let (start, end) = (&geometry.vertices[i], &geometry.vertices[i + 1]);
+            let segment = voxel_to_mm * (end.position - start.position);
+            let length = segment.norm();
+            if length <= f32::EPSILON {
+                continue;
+            }
+            let direction = segment / length;
+            let normal = transport(Vector3::zeros(), &direction);
+            let binormal = direction.cross(&normal);
+            let color = start.color;
+            let start_mm = voxel_to_mm * start.position.coords;
+
+            while next_arrow <= arc_length + length {
+                let tip = start_mm + direction * (next_arrow - arc_length);
+                for wing in [normal, -normal, binormal, -binormal] {
+                    let tail = tip + (wing * 0.5 - direction) * arrows.size;
+
+                    let first = geometry.vertices.len();
+                    geometry.ranges.push(first..first + 1);
+                    geometry
+                        .vertices
+                        .extend([tail, tip].map(|point| FiberVertex {
+                            position: (mm_to_voxel * point).into(),
+                            color,
+                        }));
+                }
+                next_arrow += arrows.spacing;
+            }
+            arc_length += length;
+        }
+    }
+}
+
+fn sprites(
+    vertices: &[FiberVertex],
+    ranges: &[Range<usize>],
+    coloring: EndpointColoring,
+) -> Vec<SpriteVertex> {
+    ranges
+        .iter()
+        .flat_map(|range| {
+            [range.start, range.end]
+                .into_iter()
+                .enumerate()
+                .map(|(end, i)| {
+                    let color = match coloring {
+                        EndpointColoring::End => colormap::category(end as i64),
+                        EndpointColoring::Streamline => vertices[i].color,
+                    };
+                    SpriteVertex {
+                        center: vertices[i].position,
+                        color,
+                    }
+                })
+        })
+        .collect()
+}
+
+/// Builds a tube around each streamline, using parallel transport frames to avoid twisting.
+///
+/// The tubes are built in mm, then brought back to voxel space.
+fn tubes(
+    vertices: &[FiberVertex],
+    ranges: &[Range<usize>],
+    tube: &Tube,
+    voxel_to_mm: &Matrix3<f32>,
+    mm_to_voxel: &Matrix3<f32>,
+) -> (Vec<TubeVertex>, Vec<u32>) {
+    let sides = tube.sides as usize;
+
+    let mut tube_vertices = Vec::with_capacity(vertices.len() * sides);
+    let mut tube_indices = vec![];
+
+    for range in ranges {
+        let streamline = &vertices[range.start..=range.end];
+        let points: Vec<Point3<f32>> = streamline
+            .iter()
+            .map(|v| (voxel_to_mm * v.position.coords).into())
+            .collect();
+
+        let first_ring = tube_vertices.len() as u32;
+        let mut normal = Vector3::zeros();
+
+        for (i, vertex) in streamline.iter().enumerate() {
+            let before = points[i.saturating_sub(1)];
+            let after = points[(i + 1).min(points.len() - 1)];
+            let tangent = (after - before)
+                .try_normalize(f32::EPSILON)
+                .unwrap_or(Vector3::z());
+
+            normal = transport(normal, &tangent);
+            let binormal = tangent.cross(&normal);
+
+            for side in 0..sides {
+                let angle = side as f32 / sides as f32 * std::f32::consts::TAU;
+                let direction = normal * angle.cos() + binormal * angle.sin();
+                let offset = mm_to_voxel * direction;
+
+                tube_vertices.push(TubeVertex {
+                    position: vertex.position + offset * tube.radius,
+                    // Normals are transformed by the inverse transpose of mm_to_voxel
+                    normal: (voxel_to_mm.transpose() * direction).normalize(),
+                    color: vertex.color,
+                });
+            }
+        }
+
+        for i in 0..range.len() as u32 {
+            let (ring, next_ring) = (
+                first_ring + i * tube.sides,
+                first_ring + (i + 1) * tube.sides,
+            );
+            for side in 0..tube.sides {
+                let next_side = (side + 1) % tube.sides;
+                tube_indices.extend([
+                    ring + side,
+                    next_ring + side,
+                    next_ring + next_side,
+                    ring + side,
+                    next_ring + next_side,
+                    ring + next_side,
+                ]);
+            }
+        }
+    }
+
+    (tube_vertices, tube_indices)
+}
+
+/// Projects the previous normal on the plane perpendicular to `tangent`.
+fn transport(previous: Vector3<f32>, tangent: &Vector3<f32>) -> Vector3<f32> {
+    let projected = previous - tangent * previous.dot(tangent);
+    projected.try_normalize(1e-3).unwrap_or_else(|| {
+        // First point, or sharp turn: any perpendicular vector will do
+        let axis = if tangent.x.abs() < 0.9 {
+            Vector3::x()
+        } else {
+            Vector3::y()
+        };
+        tangent.cross(&axis).normalize()
+    })
+}
@@ -0,0 +1,175 @@
+use nalgebra::Point3;
+
+/// Parameters of the QuickBundles clustering, from Garyfallidis et al. (2012).
+#[derive(Clone, Copy)]
+pub struct Clustering {
+    /// Maximum MDF distance between a streamline and the centroid of its cluster, in mm
+    pub threshold: f32,
+    /// Number of points of the resampled streamlines
+    pub nb_points: usize,
+    /// Draw the centroids instead of the streamlines
+    pub centroids_only: bool,
+}
+
+struct Centroid {
+    /// Mean of the resampled streamlines of the cluster
+    points: Vec<Point3<f32>>,
+    count: usize,
+}
+
+/// Streamlines are assigned one by one, so the whole tractogram never needs to be resampled.
+pub struct QuickBundles {
+    threshold: f32,
+    nb_points: usize,
+    centroids: Vec<Centroid>,
+}
+
+impl QuickBundles {
+    pub fn new(clustering: &Clustering) -> Self {
+        Self {
+            threshold: clustering.threshold,
+            nb_points: clustering.nb_points.max(2),
+            centroids: vec![],
+        }
+    }
+
+    /// Returns the cluster of the streamline, in mm, creating a new cluster if none is close
+    /// enough.
+    pub fn assign(&mut self, points: &[Point3<f32>]) -> usize {
+        let mut streamline = resample(points, self.nb_points);
+
+        let closest = self
+            .centroids
+            .iter()
+            .enumerate()
+            .map(|(i, centroid)| (i, mdf(&centroid.points, &streamline)))
+            .min_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b));
+
+        match closest {
+            Some((i, (distance, flipped))) if distance < self.threshold => {
+                if flipped {
+                    streamline.reverse();
+                }
+                let centroid = &mut self.centroids[i];
+                centroid.count += 1;
+                let weight = 1. / centroid.count as f32;
+                for (mean, point) in centroid.points.iter_mut().zip(&streamline) {
+                    *mean += (point - *mean) * weight;
+                }
+                i
+            }
+            _ => {
+                self.centroids.push(Centroid {
+                    points: streamline,
+                    count: 1,
+                });
+                self.centroids.len() - 1
+            }
+        }
+    }
+
+    /// Centroid of each cluster, in mm.
+    pub fn centroids(&self) -> impl Iterator<Item = &[Point3<f32>]> {
+        self.centroids
+            .iter()
+            .map(|centroid| centroid.points.as_slice())
+    }
+}
+
+/// Minimum average direct-flip distance. Also returns whether `b` is flipped.
+fn mdf(a: &[Point3<f32>], b: &[Point3<f32>]) -> (f32, bool) {
+    let mean_distance = |b: &mut dyn Iterator<Item = &Point3<f32>>| {
+        a.iter().zip(b).map(|(p, q)| (p - q).norm()).sum::<f32>() / a.len() as f32
+    };
+    let direct = mean_distance(&mut b.iter());
+    let flipped = mean_distance(&mut b.iter().rev());
+
+    if flipped < direct {
+        (flipped, true)
+    } else {
+        (direct, false)
+    }
+}
+
+/// Resamples the streamline to `nb_points` points evenly spaced along its length.
+fn resample(points: &[Point3<f32>], nb_points: usize) -> Vec<Point3<f32>> {
+    let cumulative_lengths: Vec<f32> = std::iter::once(0.)
+        .chain(points.windows(2).scan(0., |length, w| {
+            *length += (w[1] - w[0]).norm();
+            Some(*length)
+        }))
+        .collect();
+    let total_length = *cumulative_lengths.last().expect("Streamlines have points");
+
+    let mut segment = 0;
+    (0..nb_points)
+        .map(|i| {
+            let target = total_length * i as f32 / (nb_points - 1) as f32;
+            while segment + 2 < points.len() && cumulative_lengths[segment + 1] < target {
+                segment += 1;
+            }
+            if points.len() == 1 {
+                return points[0];
+            }
+            let (start, end) = (cumulative_lengths[segment], cumulative_lengths[segment + 1]);
+            let t = if end > start {
+                ((target - start) / (end - start)).clamp(0., 1.)
+            } else {
+                0.
+            };
+            points[segment] + (points[segment + 1] - points[segment]) * t
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quickbundles() -> QuickBundles {
+        QuickBundles::new(&Clustering {
+            threshold: 10.,
+            nb_points: 12,
+            centroids_only: false,
+        })
+    }
+
+    /// Straight streamline along x, from 0 to 50 mm.
+    fn line(y: f32, z: f32) -> Vec<Point3<f32>> {
+        (0..=10).map(|i| Point3::new(i as f32 * 5., y, z)).collect()
+    }
+
+    #[test]
+    fn parallel_bundles_give_two_clusters() {
+        let mut quickbundles = quickbundles();
+        let first: Vec<usize> = [0., 1., 2.]
+            .iter()
+            .map(|&y| quickbundles.assign(&line(y, 0.)))
+            .collect();
+        let second: Vec<usize> = [30., 31., 32.]
+            .iter()
+            .map(|&y| quickbundles.assign(&line(y, 0.)))
+            .collect();
+
+        assert_eq!(quickbundles.centroids().count(), 2);
+        assert!(first.iter().all(|&c| c == first[0]));
+        assert!(second.iter().all(|&c| c == second[0]));
+        assert_ne!(first[0], second[0]);
+    }
+
+    #[test]
+    fn reversed_streamline_joins_the_same_cluster() {
+        let mut quickbundles = quickbundles();
+        let streamline = line(0., 0.);
+        let reversed: Vec<Point3<f32>> = line(2., 0.).into_iter().rev().collect();
+
+        let cluster = quickbundles.assign(&streamline);
+        assert_eq!(quickbundles.assign(&reversed), cluster);
+        assert_eq!(quickbundles.centroids().count(), 1);
+
+        // The flipped streamline is averaged point to point with the direct one
+        let centroid = quickbundles.centroids().next().unwrap();
+        assert!((centroid[0] - Point3::new(0., 1., 0.)).norm() < 1e-4);
+        assert!((centroid[11] - Point3::new(50., 1., 0.)).norm() < 1e-4);
+    }
+}
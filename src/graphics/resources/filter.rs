@@ -0,0 +1,90 @@
+use nalgebra::{Matrix3, Point3};
+use ndarray::Array3;
+
+use super::{fibers::Streamline, measure};
+
+/// Region of interest, in the voxel space of the image.
+pub enum Roi {
+    /// Voxels of the image grid
+    Mask(Array3<bool>),
+    /// Center in voxel space and radius in mm
+    Sphere { center: Point3<f32>, radius: f32 },
+}
+
+impl Roi {
+    fn contains(&self, point: &Point3<f32>, voxel_to_mm: &Matrix3<f32>) -> bool {
+        match self {
+            Roi::Mask(mask) => {
+                if point.iter().any(|&c| c < 0.) {
+                    return false;
+                }
+                let p = point.map(|c| c as usize);
+                mask.get((p.x, p.y, p.z)).copied().unwrap_or(false)
+            }
+            Roi::Sphere { center, radius } => (voxel_to_mm * (point - center)).norm() <= *radius,
+        }
+    }
+}
+
+/// Selects the streamlines drawn, before their geometry is built.
+pub struct Filter {
+    /// In mm
+    pub min_length: Option<f32>,
+    /// In mm
+    pub max_length: Option<f32>,
+    /// Regions that must all be reached
+    pub include: Vec<Roi>,
+    /// Regions that must not be reached
+    pub exclude: Vec<Roi>,
+    /// Only the first and last points of the streamlines are tested against the regions
+    pub endpoints: bool,
+}
+
+impl Filter {
+    /// Whether all the streamlines are kept.
+    pub fn keeps_all(&self) -> bool {
+        self.min_length.is_none()
+            && self.max_length.is_none()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+    }
+
+    pub fn keeps(&self, streamline: &Streamline, voxel_to_mm: &Matrix3<f32>) -> bool {
+        self.keeps_length(streamline, voxel_to_mm)
+            && self
+                .include
+                .iter()
+                .all(|roi| self.reaches(streamline, roi, voxel_to_mm))
+            && !self
+                .exclude
+                .iter()
+                .any(|roi| self.reaches(streamline, roi, voxel_to_mm))
+    }
+
+    fn keeps_length(&self, streamline: &Streamline, voxel_to_mm: &Matrix3<f32>) -> bool {
+        if self.min_length.is_none() && self.max_length.is_none() {
+            return true;
+        }
+        let points_mm: Vec<Point3<f32>> = streamline
+            .points
+            .iter()
+            .map(|p| (voxel_to_mm * p.coords).into())
+            .collect();
+        let length = measure::length(&points_mm);
+
+        self.min_length.is_none_or(|min| length >= min)
+            && self.max_length.is_none_or(|max| length <= max)
+    }
+
+    fn reaches(&self, streamline: &Streamline, roi: &Roi, voxel_to_mm: &Matrix3<f32>) -> bool {
+        let points = &streamline.points;
+        if self.endpoints {
+            [points.first(), points.last()]
+                .into_iter()
+                .flatten()
+                .any(|point| roi.contains(point, voxel_to_mm))
+        } else {
+            points.iter().any(|point| roi.contains(point, voxel_to_mm))
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use nalgebra::{Matrix3, Point3, Vector3};
+
+use super::fibers::Streamline;
+
+/// Below this curvature, in 1/mm, the osculating plane and the torsion are undefined.
+const MIN_CURVATURE: f64 = 1e-6;
+
+/// Value associated to each vertex for the colormap-based coloring.
+#[derive(Clone, Copy)]
+pub enum Measure {
+    /// Per-point scalar of the TrackVis file, by index
+    Scalar(usize),
+    /// Per-streamline property of the TrackVis file, by index
+    Property(usize),
+    /// Total arc length of the streamline, in mm
+    Length,
+    /// Local curvature, in 1/mm
+    Curvature,
+    /// Local torsion, in 1/mm
+    Torsion,
+    /// Normalized arc length, from 0 at the first point to 1 at the last one
+    Position,
+}
+
+impl Measure {
+    /// Returns one value per point of the streamline.
+    pub fn values(&self, streamline: &Streamline, voxel_to_mm: &Matrix3<f32>) -> Vec<f32> {
+        let points_mm = || -> Vec<Point3<f32>> {
+            let to_mm = |p: &Point3<f32>| (voxel_to_mm * p.coords).into();
+            streamline.points.iter().map(to_mm).collect()
+        };
+
+        match *self {
+            Measure::Scalar(index) => streamline.scalar(index).collect(),
+            Measure::Property(index) => vec![streamline.properties[index]; streamline.len()],
+            Measure::Length => vec![length(&points_mm()); streamline.len()],
+            Measure::Curvature => extend_to_ends(curvatures(&points_mm()), streamline.len()),
+            Measure::Torsion => extend_to_ends(torsions(&points_mm()), streamline.len()),
+            Measure::Position => positions(&points_mm()),
+        }
+    }
+}
+
+pub fn length(points: &[Point3<f32>]) -> f32 {
+    points.windows(2).map(|w| (w[1] - w[0]).norm()).sum()
+}
+
+/// Arc length at each point, divided by the total length.
+fn positions(points: &[Point3<f32>]) -> Vec<f32> {
+    let mut arc_length = 0.;
+    let mut positions: Vec<f32> = std::iter::once(0.)
+        .chain(points.windows(2).map(|w| {
+            arc_length += (w[1] - w[0]).norm();
+            arc_length
+        }))
+        .collect();
+
+    if arc_length > f32::EPSILON {
+        positions.iter_mut().for_each(|p| *p /= arc_length);
+    }
+    positions
+}
+
+/// Curvature of each inner point, using central differences.
+fn curvatures(points: &[Point3<f32>]) -> Vec<f32> {
+    points
+        .windows(3)
+        .map(|w| {
+            let [p0, p1, p2] = relative_to(w[1], w);
+            let d1 = (p2 - p0) / 2.;
+            let d2 = p2 - 2. * p1 + p0;
+            let speed = d1.norm();
+
+            if speed > f64::EPSILON {
+                (d1.cross(&d2).norm() / speed.powi(3)) as f32
+            } else {
+                0.
+            }
+        })
+        .collect()
+}
+
+/// Torsion of each point that has two neighbours on both sides, using central differences.
+/// The differences scale with powers of the step size, so the straight parts are detected
+/// with the curvature, which doesn't.
+fn torsions(points: &[Point3<f32>]) -> Vec<f32> {
+    points
+        .windows(5)
+        .map(|w| {
+            let [p0, p1, _, p3, p4] = relative_to(w[2], w);
+            let d1 = (p3 - p1) / 2.;
+            let d2 = p3 + p1; // The center is the origin
+            let d3 = (p4 - 2. * p3 + 2. * p1 - p0) / 2.;
+            let normal = d1.cross(&d2);
+
+            if normal.norm() > MIN_CURVATURE * d1.norm().powi(3) {
+                (normal.dot(&d3) / normal.norm_squared()) as f32
+            } else {
+                0.
+            }
+        })
+        .collect()
+}
+
+/// Points of the window in f64, relative to `center`, to limit the cancellation in the
+/// finite differences.
+fn relative_to<const N: usize>(center: Point3<f32>, window: &[Point3<f32>]) -> [Vector3<f64>; N] {
+    std::array::from_fn(|i| window[i].coords.cast::<f64>() - center.coords.cast::<f64>())
+}
+
+/// Pads the values computed on the inner points, so that the ends use their closest value.
+fn extend_to_ends(inner: Vec<f32>, len: usize) -> Vec<f32> {
+    let Some((&first, &last)) = inner.first().zip(inner.last()) else {
+        return vec![0.; len];
+    };
+    let pad = (len - inner.len()) / 2;
+
+    let mut values = Vec::with_capacity(len);
+    values.resize(pad, first);
+    values.extend(inner);
+    values.resize(len, last);
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Helix of radius 2 mm and rise 1 mm per radian, sampled every `step` mm of arc length,
+    /// far from the origin.
+    fn helix(step: f64) -> Vec<Point3<f32>> {
+        let (radius, rise) = (2f64, 1f64);
+        let dt = step / radius.hypot(rise);
+        (0..40)
+            .map(|i| {
+                let t = i as f64 * dt;
+                let point = [
+                    radius * t.cos() + 60.,
+                    radius * t.sin() + 80.,
+                    rise * t + 40.,
+                ];
+                Point3::from(point.map(|c| c as f32))
+            })
+            .collect()
+    }
+
+    fn assert_close(values: &[f32], expected: f32, tolerance: f32) {
+        for value in values {
+            assert!(
+                (value - expected).abs() < tolerance * expected,
+                "{value} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn curvature_of_a_helix() {
+        // a / (a² + b²)
+        assert_close(&curvatures(&helix(0.5)), 0.4, 0.01);
+        assert_close(&curvatures(&helix(0.1)), 0.4, 0.01);
+    }
+
+    #[test]
+    fn torsion_of_a_helix() {
+        // b / (a² + b²)
+        assert_close(&torsions(&helix(0.5)), 0.2, 0.01);
+        // The third differences are close to the rounding of the f32 coordinates
+        assert_close(&torsions(&helix(0.1)), 0.2, 0.1);
+    }
+
+    #[test]
+    fn line_has_no_curvature_nor_torsion() {
+        let line: Vec<_> = (0..10)
+            .map(|i| Point3::new(i as f32 * 0.1, 50., 50.))
+            .collect();
+        assert!(curvatures(&line).iter().all(|&k| k == 0.));
+        assert!(torsions(&line).iter().all(|&t| t == 0.));
+    }
+}
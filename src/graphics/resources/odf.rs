@@ -0,0 +1,269 @@
+use std::{cmp::Ordering, collections::HashMap, f64::consts::SQRT_2};
+
+use nalgebra::{Matrix3, Point3, Vector3};
+use ndarray::{s, Array2, Array4, Axis};
+use wgpu::Buffer;
+
+use super::{buffer, slice_voxels, vertex::TubeVertex, Client};
+use crate::slicer::Slice;
+
+/// Number of subdivisions of the icosahedron, which gives 162 vertices per glyph.
+const SPHERE_SUBDIVISIONS: usize = 2;
+
+/// Real and symmetric spherical harmonics basis of the coefficients, in the scanner RAS+ axes.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ShBasis {
+    /// MRtrix3, without the Condon-Shortley phase
+    Mrtrix,
+    /// Descoteaux et al. (2007), as in Dipy, with the Condon-Shortley phase
+    Descoteaux,
+}
+
+/// Spherical harmonics ODFs, like the output of a CSD, drawn as deformed spheres at each
+/// voxel of the slices.
+pub struct OdfField {
+    /// `(x, y, z, nb_coefficients)`
+    values: Array4<f32>,
+    /// Basis functions at each vertex of the sphere, `(nb_vertices, nb_coefficients)`
+    basis: Array2<f32>,
+    /// Sphere vertices scaled to the glyph radius, in voxel space
+    offsets: Vec<Vector3<f32>>,
+    /// Absolute value of the sphere directions, in mm
+    colors: Vec<Vector3<f32>>,
+    triangles: Vec<[u32; 3]>,
+    /// The triangles are clockwise in voxel space when the affine is mirrored
+    mirrored: bool,
+}
+
+impl OdfField {
+    /// `order` truncates the coefficients, which are all used by default. `scale` is the
+    /// glyphs size relative to the smallest voxel side.
+    pub fn new(
+        values: Array4<f32>,
+        sh_basis: ShBasis,
+        order: Option<usize>,
+        scale: f32,
+        mm_to_voxel: Matrix3<f32>,
+        spacing: Vector3<f32>,
+    ) -> Self {
+        let max_order = (0..=32)
+            .step_by(2)
+            .find(|&l| nb_coefficients(l) == values.dim().3)
+            .expect("The SH image must have (l + 1)(l + 2) / 2 components, for an even order l.");
+        let order = order.unwrap_or(max_order);
+        if order > max_order || !order.is_multiple_of(2) {
+            panic!("The SH order must be even and at most {max_order}.");
+        }
+        let values = values.slice_move(s![.., .., .., ..nb_coefficients(order)]);
+
+        let (directions, triangles) = sphere(SPHERE_SUBDIVISIONS);
+        let basis = Array2::from_shape_fn(
+            (directions.len(), nb_coefficients(order)),
+            |(vertex, coefficient)| {
+                let direction = directions[vertex].cast::<f64>();
+                let theta = direction.z.clamp(-1., 1.).acos();
+                let phi = direction.y.atan2(direction.x);
+                sh_function(sh_basis, coefficient, theta, phi) as f32
+            },
+        );
+
+        let radius = scale * spacing.min() / 2.;
+        Self {
+            values,
+            basis,
+            offsets: directions
+                .iter()
+                .map(|direction| mm_to_voxel * direction * radius)
+                .collect(),
+            colors: directions.iter().map(|direction| direction.abs()).collect(),
+            triangles,
+            mirrored: mm_to_voxel.determinant() < 0.,
+        }
+    }
+
+    /// One glyph per voxel of the slice, skipping the empty voxels. Returns the vertices,
+    /// the triangle indices and the number of indices.
+    pub fn meshes(&self, slice: &Slice, client: &Client) -> (Buffer, Buffer, u32) {
+        let axis = slice.view.axis() as usize;
+        let values = self.values.index_axis(Axis(axis), slice.index);
+
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        for ((u, v), center) in slice_voxels(slice) {
+            let coefficients = values.slice(s![u, v, ..]);
+            if coefficients.iter().any(|c| !c.is_finite()) || coefficients.iter().all(|&c| c == 0.)
+            {
+                continue; // Background
+            }
+
+            let amplitudes = self.basis.dot(&coefficients);
+            let amplitudes = amplitudes
+                .as_slice()
+                .expect("The amplitudes are contiguous");
+
+            let first = vertices.len() as u32;
+            vertices.extend(self.glyph(amplitudes, center));
+            indices.extend(self.triangles.iter().flatten().map(|i| first + i));
+        }
+
+        let vertex_buffer = buffer::init_vertices("Odf", &vertices, &client.device);
+        let index_buffer = buffer::init_indices("Odf", &indices, &client.device);
+        (vertex_buffer, index_buffer, indices.len() as u32)
+    }
+
+    /// Deforms the sphere by the min-max normalized amplitudes.
+    fn glyph(&self, amplitudes: &[f32], center: Point3<f32>) -> Vec<TubeVertex> {
+        let (min, max) = amplitudes
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), &a| {
+                (min.min(a), max.max(a))
+            });
+        let range = max - min;
+
+        let mut vertices: Vec<TubeVertex> = self
+            .offsets
+            .iter()
+            .zip(&self.colors)
+            .zip(amplitudes)
+            .map(|((offset, color), amplitude)| {
+                let radius = if range > f32::EPSILON {
+                    (amplitude - min) / range
+                } else {
+                    1. // Isotropic
+                };
+                TubeVertex {
+                    position: center + offset * radius,
+                    normal: Vector3::zeros(),
+                    color: *color,
+                }
+            })
+            .collect();
+
+        // Area-weighted normals of the deformed sphere
+        for &[a, b, c] in &self.triangles {
+            let [a, b, c] = [a, b, c].map(|i| i as usize);
+            let mut normal = (vertices[b].position - vertices[a].position)
+                .cross(&(vertices[c].position - vertices[a].position));
+            if self.mirrored {
+                normal = -normal;
+            }
+            for i in [a, b, c] {
+                vertices[i].normal += normal;
+            }
+        }
+        for (vertex, offset) in vertices.iter_mut().zip(&self.offsets) {
+            vertex.normal = vertex.normal.try_normalize(f32::EPSILON).unwrap_or(*offset);
+        }
+        vertices
+    }
+}
+
+fn nb_coefficients(order: usize) -> usize {
+    (order + 1) * (order + 2) / 2
+}
+
+/// Real spherical harmonic of the `index`-th coefficient, ordered by even degree `l`, then
+/// by order `m` from `-l` to `l`. `theta` is the polar angle and `phi` the azimuth.
+fn sh_function(basis: ShBasis, index: usize, theta: f64, phi: f64) -> f64 {
+    let l = (0..)
+        .step_by(2)
+        .find(|&l| nb_coefficients(l) > index)
+        .unwrap();
+    let m = index as i64 - nb_coefficients(l) as i64 + l as i64 + 1;
+    let k = m.unsigned_abs() as usize;
+
+    let legendre = normalized_legendre(l, k, theta.cos());
+    let (cos, sin) = ((k as f64 * phi).cos(), (k as f64 * phi).sin());
+    let condon_shortley = if k.is_multiple_of(2) { 1. } else { -1. };
+    match (basis, m.cmp(&0)) {
+        (_, Ordering::Equal) => legendre,
+        (ShBasis::Mrtrix, Ordering::Less) => SQRT_2 * legendre * sin,
+        (ShBasis::Mrtrix, Ordering::Greater) => SQRT_2 * legendre * cos,
+        (ShBasis::Descoteaux, Ordering::Less) => condon_shortley * SQRT_2 * legendre * cos,
+        (ShBasis::Descoteaux, Ordering::Greater) => condon_shortley * SQRT_2 * legendre * sin,
+    }
+}
+
+/// Associated Legendre polynomial `P_l^m(x)`, without the Condon-Shortley phase, times the
+/// normalization of the spherical harmonics.
+fn normalized_legendre(l: usize, m: usize, x: f64) -> f64 {
+    // P_m^m, then upward recurrence on the degree
+    let double_factorial: f64 = (1..=m).map(|i| (2 * i - 1) as f64).product();
+    let mut previous = 0.;
+    let mut current = double_factorial * (1. - x * x).max(0.).powf(m as f64 / 2.);
+    for degree in m + 1..=l {
+        let next = ((2 * degree - 1) as f64 * x * current - (degree + m - 1) as f64 * previous)
+            / (degree - m) as f64;
+        previous = current;
+        current = next;
+    }
+
+    let factorial_ratio: f64 = (l - m + 1..=l + m).map(|i| 1. / i as f64).product();
+    let normalization = ((2 * l + 1) as f64 / (4. * std::f64::consts::PI) * factorial_ratio).sqrt();
+    normalization * current
+}
+
+/// Unit icosphere, with counterclockwise triangles seen from outside.
+fn sphere(subdivisions: usize) -> (Vec<Vector3<f32>>, Vec<[u32; 3]>) {
+    let t = (1. + 5f32.sqrt()) / 2.;
+    let mut vertices: Vec<Vector3<f32>> = [
+        [-1., t, 0.],
+        [1., t, 0.],
+        [-1., -t, 0.],
+        [1., -t, 0.],
+        [0., -1., t],
+        [0., 1., t],
+        [0., -1., -t],
+        [0., 1., -t],
+        [t, 0., -1.],
+        [t, 0., 1.],
+        [-t, 0., -1.],
+        [-t, 0., 1.],
+    ]
+    .iter()
+    .map(|&v| Vector3::from(v).normalize())
+    .collect();
+    let mut triangles = vec![
+        [0, 11, 5],
+        [0, 5, 1],
+        [0, 1, 7],
+        [0, 7, 10],
+        [0, 10, 11],
+        [1, 5, 9],
+        [5, 11, 4],
+        [11, 10, 2],
+        [10, 7, 6],
+        [7, 1, 8],
+        [3, 9, 4],
+        [3, 4, 2],
+        [3, 2, 6],
+        [3, 6, 8],
+        [3, 8, 9],
+        [4, 9, 5],
+        [2, 4, 11],
+        [6, 2, 10],
+        [8, 6, 7],
+        [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        let mut middles = HashMap::new();
+        let mut middle = |a: u32, b: u32, vertices: &mut Vec<Vector3<f32>>| {
+            *middles.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                let middle = (vertices[a as usize] + vertices[b as usize]).normalize();
+                vertices.push(middle);
+                vertices.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let ab = middle(a, b, &mut vertices);
+                let bc = middle(b, c, &mut vertices);
+                let ca = middle(c, a, &mut vertices);
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+    (vertices, triangles)
+}
@@ -0,0 +1,87 @@
+use nalgebra::{Matrix3, Vector3};
+use ndarray::{s, Array4, Axis, Zip};
+use wgpu::Buffer;
+
+use super::{buffer, slice_voxels, vertex::FiberVertex, Client};
+use crate::slicer::Slice;
+
+/// Peak directions, like the output of MRtrix's `sh2peaks` or Dipy, drawn as line segments
+/// centered on the voxels of the slices.
+pub struct PeakField {
+    /// `(x, y, z, 3 * nb_peaks)` scanner RAS+ vectors, whose norm is the amplitude
+    values: Array4<f32>,
+    mm_to_voxel: Matrix3<f32>,
+    /// Peaks with a smaller amplitude are hidden
+    threshold: f32,
+    /// Length, in mm, of the peaks with the largest amplitude
+    max_length: f32,
+    max_amplitude: f32,
+}
+
+impl PeakField {
+    /// `scale` is the length of the largest peaks, relative to the smallest voxel side.
+    pub fn new(
+        values: Array4<f32>,
+        threshold: f32,
+        scale: f32,
+        mm_to_voxel: Matrix3<f32>,
+        spacing: Vector3<f32>,
+    ) -> Self {
+        if values.dim().3 % 3 != 0 {
+            panic!("The peaks image must have 3 components per peak.");
+        }
+        let max_amplitude = (0..values.dim().3 / 3)
+            .map(|peak| {
+                let component = |c| values.index_axis(Axis(3), 3 * peak + c);
+                Zip::from(component(0))
+                    .and(component(1))
+                    .and(component(2))
+                    .fold(0., |max: f32, &x, &y, &z| {
+                        let amplitude = Vector3::new(x, y, z).norm();
+                        if amplitude.is_finite() {
+                            max.max(amplitude)
+                        } else {
+                            max
+                        }
+                    })
+            })
+            .fold(0., f32::max);
+
+        Self {
+            values,
+            mm_to_voxel,
+            threshold,
+            max_length: scale * spacing.min(),
+            max_amplitude,
+        }
+    }
+
+    /// Two vertices per peak of the voxels of the slice, for a line list.
+    pub fn vertices(&self, slice: &Slice, client: &Client) -> (Buffer, u32) {
+        let axis = slice.view.axis() as usize;
+        let values = self.values.index_axis(Axis(axis), slice.index);
+
+        let mut vertices = vec![];
+        for ((u, v), center) in slice_voxels(slice) {
+            let peaks = values.slice(s![u, v, ..]).to_vec();
+            for peak in peaks.chunks_exact(3) {
+                let peak = Vector3::new(peak[0], peak[1], peak[2]);
+                let amplitude = peak.norm();
+                if !amplitude.is_finite() || amplitude <= self.threshold.max(f32::EPSILON) {
+                    continue;
+                }
+                let half_length = amplitude / self.max_amplitude * self.max_length / 2.;
+                let offset = self.mm_to_voxel * (peak / amplitude * half_length);
+                let color = (peak / amplitude).abs();
+
+                vertices.extend(
+                    [center - offset, center + offset]
+                        .map(|position| FiberVertex { position, color }),
+                );
+            }
+        }
+
+        let buffer = buffer::init_vertices("Peaks", &vertices, &client.device);
+        (buffer, vertices.len() as u32)
+    }
+}
@@ -0,0 +1,20 @@
+use nalgebra::{Point3, Vector3};
+
+use super::vertex::SpriteVertex;
+
+/// Points drawn as disks, like the endpoint sprites, e.g. the directions of a gradient table.
+pub struct PointCloud {
+    /// In voxel space
+    pub positions: Vec<Point3<f32>>,
+    pub colors: Vec<Vector3<f32>>,
+}
+
+impl PointCloud {
+    pub fn vertices(&self) -> Vec<SpriteVertex> {
+        self.positions
+            .iter()
+            .zip(&self.colors)
+            .map(|(&center, &color)| SpriteVertex { center, color })
+            .collect()
+    }
+}
@@ -0,0 +1,163 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Keeps a subset of the streamlines while they are read, so that huge tractograms are never
+/// fully loaded.
+#[derive(Clone, Copy)]
+pub struct Subsampling {
+    pub max_streamlines: Option<usize>,
+    /// In `[0, 1]`
+    pub keep_fraction: f32,
+    pub method: SubsamplingMethod,
+    /// Seed of the random method, so that the same streamlines are kept for all slices
+    pub seed: u64,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum SubsamplingMethod {
+    /// Uniformly random streamlines
+    Random,
+    /// Every k-th streamline
+    Stride,
+}
+
+impl Subsampling {
+    /// `total` is the number of streamlines announced by the file header, 0 if unknown, like
+    /// after a filter. When known, exactly the expected number of streamlines is kept.
+    /// Otherwise, the random method still keeps `max_streamlines` of them, which are buffered,
+    /// and the stride method keeps the first ones.
+    pub fn apply<'a, T: 'a>(
+        &self,
+        items: impl Iterator<Item = T> + 'a,
+        total: usize,
+    ) -> Box<dyn Iterator<Item = T> + 'a> {
+        let max_streamlines = self.max_streamlines.unwrap_or(usize::MAX);
+        let keep_fraction = self.keep_fraction;
+        let method = self.method;
+
+        let target = (total > 0).then(|| {
+            let kept = (total as f32 * keep_fraction).round() as usize;
+            kept.min(max_streamlines)
+        });
+        let stride = match target {
+            Some(target) => total.checked_div(target).unwrap_or(total),
+            None => (1. / keep_fraction).round() as usize,
+        }
+        .max(1);
+
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let (mut remaining, mut needed) = (total, target.unwrap_or(0));
+
+        let sampled = items
+            .enumerate()
+            .filter(move |(i, _)| match (method, target) {
+                (SubsamplingMethod::Stride, _) => i % stride == 0,
+                // Selection sampling, Knuth's algorithm S
+                (SubsamplingMethod::Random, Some(_)) => {
+                    let keep = remaining > 0 && rng.gen_range(0..remaining) < needed;
+                    remaining = remaining.saturating_sub(1);
+                    needed -= keep as usize;
+                    keep
+                }
+                (SubsamplingMethod::Random, None) => rng.gen::<f32>() < keep_fraction,
+            });
+
+        match (method, target, self.max_streamlines) {
+            (SubsamplingMethod::Random, None, Some(size)) => {
+                let rng = StdRng::seed_from_u64(self.seed.wrapping_add(1));
+                Box::new(reservoir(sampled, size, rng).into_iter())
+            }
+            _ => Box::new(
+                sampled
+                    .map(|(_, item)| item)
+                    .take(target.unwrap_or(max_streamlines)),
+            ),
+        }
+    }
+}
+
+/// Uniformly random `size` items of an iterator of unknown length, with Vitter's algorithm R.
+/// They keep the order of their indices.
+fn reservoir<T>(items: impl Iterator<Item = (usize, T)>, size: usize, mut rng: StdRng) -> Vec<T> {
+    let mut reservoir = Vec::with_capacity(size);
+    for (seen, item) in items.enumerate() {
+        if seen < size {
+            reservoir.push(item);
+        } else {
+            let replaced = rng.gen_range(0..=seen);
+            if replaced < size {
+                reservoir[replaced] = item;
+            }
+        }
+    }
+    reservoir.sort_by_key(|(index, _)| *index);
+    reservoir.into_iter().map(|(_, item)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subsampling(
+        method: SubsamplingMethod,
+        keep_fraction: f32,
+        max: Option<usize>,
+    ) -> Subsampling {
+        Subsampling {
+            max_streamlines: max,
+            keep_fraction,
+            method,
+            seed: 42,
+        }
+    }
+
+    fn kept(subsampling: &Subsampling, total: usize) -> Vec<usize> {
+        subsampling.apply(0..total, total).collect()
+    }
+
+    #[test]
+    fn stride_keeps_the_expected_count() {
+        let fraction = subsampling(SubsamplingMethod::Stride, 0.25, None);
+        assert_eq!(
+            kept(&fraction, 100),
+            (0..100).step_by(4).collect::<Vec<_>>()
+        );
+
+        let max = subsampling(SubsamplingMethod::Stride, 1., Some(30));
+        assert_eq!(kept(&max, 100).len(), 30);
+    }
+
+    #[test]
+    fn random_keeps_the_expected_count() {
+        for (total, fraction, max, expected) in [
+            (1000, 0.1, None, 100),
+            (1000, 1., Some(250), 250),
+            (1000, 0.5, Some(250), 250),
+            (7, 1., Some(10), 7),
+        ] {
+            let random = subsampling(SubsamplingMethod::Random, fraction, max);
+            let kept = kept(&random, total);
+            assert_eq!(kept.len(), expected);
+            assert!(kept.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn random_keeps_the_maximum_of_an_unknown_total() {
+        let random = subsampling(SubsamplingMethod::Random, 1., Some(50));
+        let kept: Vec<usize> = random.apply(0..1000, 0).collect();
+        assert_eq!(kept.len(), 50);
+        assert!(kept.windows(2).all(|w| w[0] < w[1]));
+        assert!(*kept.last().unwrap() >= 50, "Not only the first items");
+
+        assert_eq!(random.apply(0..20, 0).count(), 20);
+    }
+
+    #[test]
+    fn random_depends_only_on_the_seed() {
+        let random = subsampling(SubsamplingMethod::Random, 0.2, None);
+        assert_eq!(kept(&random, 500), kept(&random, 500));
+
+        let other_seed = Subsampling { seed: 7, ..random };
+        assert_ne!(kept(&random, 500), kept(&other_seed, 500));
+    }
+}
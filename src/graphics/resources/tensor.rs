@@ -0,0 +1,145 @@
+use nalgebra::{Matrix3, Point3, SymmetricEigen, Vector3};
+use ndarray::{s, Array4, Axis};
+use wgpu::Buffer;
+
+use super::{buffer, slice_voxels, vertex::GlyphVertex, Client};
+use crate::slicer::Slice;
+
+/// Vertices of the sphere expanded in the glyph shader, `STACKS * SLICES * 6`.
+pub const GLYPH_VERTEX_COUNT: u32 = 8 * 16 * 6;
+
+/// Kindlmann's sharpness of the superquadrics edges
+const SHARPNESS: f32 = 3.;
+
+/// Order of the 6 unique components of the tensors, along the 4th dimension.
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum TensorOrder {
+    /// Dxx, Dxy, Dxz, Dyy, Dyz, Dzz, in the voxel axes
+    Fsl,
+    /// Dxx, Dyy, Dzz, Dxy, Dxz, Dyz, in the scanner RAS+ axes
+    Mrtrix,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum GlyphShape {
+    Ellipsoid,
+    /// Kindlmann's superquadrics, which disambiguate the orientation of planar tensors
+    Superquadric,
+}
+
+/// Diffusion tensors drawn as glyphs at each voxel of the slices.
+pub struct TensorField {
+    /// `(x, y, z, 6)`, ordered as Dxx, Dxy, Dxz, Dyy, Dyz, Dzz
+    values: Array4<f32>,
+    /// Converts the tensor axes to the voxel space
+    tensor_to_voxel: Matrix3<f32>,
+    voxel_to_mm: Matrix3<f32>,
+    shape: GlyphShape,
+    /// Radius of the most anisotropic direction, in mm
+    radius: f32,
+}
+
+impl TensorField {
+    /// `scale` is the glyphs size relative to the smallest voxel side.
+    pub fn new(
+        values: Array4<f32>,
+        order: TensorOrder,
+        shape: GlyphShape,
+        scale: f32,
+        voxel_to_mm: Matrix3<f32>,
+        mm_to_voxel: Matrix3<f32>,
+        spacing: Vector3<f32>,
+    ) -> Self {
+        if values.dim().3 != 6 {
+            panic!("The tensor image must have 6 components.");
+        }
+        let (values, tensor_to_voxel) = match order {
+            TensorOrder::Fsl => (values, Matrix3::from_diagonal(&spacing.map(|s| 1. / s))),
+            TensorOrder::Mrtrix => {
+                let mut fsl_order = values.clone();
+                for (i, &mrtrix) in [0, 3, 4, 1, 5, 2].iter().enumerate() {
+                    fsl_order
+                        .index_axis_mut(Axis(3), i)
+                        .assign(&values.index_axis(Axis(3), mrtrix));
+                }
+                (fsl_order, mm_to_voxel)
+            }
+        };
+
+        Self {
+            values,
+            tensor_to_voxel,
+            voxel_to_mm,
+            shape,
+            radius: scale * spacing.min() / 2.,
+        }
+    }
+
+    /// One glyph per voxel of the slice, skipping the empty tensors.
+    pub fn instances(&self, slice: &Slice, client: &Client) -> (Buffer, u32) {
+        let axis = slice.view.axis() as usize;
+        let values = self.values.index_axis(Axis(axis), slice.index);
+
+        let glyphs: Vec<GlyphVertex> = slice_voxels(slice)
+            .filter_map(|((u, v), center)| {
+                let d = values.slice(s![u, v, ..]);
+                let tensor = Matrix3::new(
+                    d[0], d[1], d[2], //
+                    d[1], d[3], d[4], //
+                    d[2], d[4], d[5],
+                );
+                self.glyph(tensor, center)
+            })
+            .collect();
+
+        let buffer = buffer::init_vertices("Glyph", &glyphs, &client.device);
+        (buffer, glyphs.len() as u32)
+    }
+
+    fn glyph(&self, tensor: Matrix3<f32>, center: Point3<f32>) -> Option<GlyphVertex> {
+        if !tensor.iter().all(|v| v.is_finite()) || tensor.trace() <= f32::EPSILON {
+            return None; // Background
+        }
+        let eigen = SymmetricEigen::new(tensor);
+        let mut order = [0, 1, 2];
+        order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+
+        let eigenvalues = Vector3::from(order.map(|i| eigen.eigenvalues[i].max(0.)));
+        let rotation = Matrix3::from_columns(&order.map(|i| eigen.eigenvectors.column(i)));
+        let sum = eigenvalues.sum();
+        if eigenvalues[0] <= f32::EPSILON {
+            return None;
+        }
+
+        // Westin's linear and planar measures
+        let linear = (eigenvalues[0] - eigenvalues[1]) / sum;
+        let planar = 2. * (eigenvalues[1] - eigenvalues[2]) / sum;
+        let shape = match self.shape {
+            GlyphShape::Ellipsoid => Vector3::new(1., 1., 1.),
+            GlyphShape::Superquadric if linear >= planar => Vector3::new(
+                (1. - planar).powf(SHARPNESS),
+                (1. - linear).powf(SHARPNESS),
+                1.,
+            ),
+            GlyphShape::Superquadric => Vector3::new(
+                (1. - linear).powf(SHARPNESS),
+                (1. - planar).powf(SHARPNESS),
+                0.,
+            ),
+        };
+
+        // Flat glyphs would have no normal
+        let radii = (eigenvalues / eigenvalues[0]).map(|r| r.max(0.05)) * self.radius;
+        let transform = self.tensor_to_voxel * rotation * Matrix3::from_diagonal(&radii);
+        let normal_transform = transform.try_inverse()?.transpose();
+
+        let principal = self.voxel_to_mm * self.tensor_to_voxel * rotation.column(0);
+        Some(GlyphVertex {
+            center,
+            transform,
+            normal_transform,
+            shape,
+            color: principal.normalize().abs(),
+        })
+    }
+}
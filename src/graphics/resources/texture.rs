@@ -1,11 +1,14 @@
 use glam::UVec2;
 use wgpu::{Extent3d, ImageCopyTexture, ImageDataLayout, Queue, TextureFormat, TextureUsages};
 
-use crate::{graphics::Client, ImageSlice};
+use crate::{graphics::Client, slicer::SliceData};
 
-pub const GRAY_FORMAT: TextureFormat = TextureFormat::R8Unorm;
 pub const COLOR_FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
 pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+/// Weighted sum of the transparent fibers colors, and sum of their weights
+pub const ACCUMULATION_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// Product of the transparent fibers transmittances
+pub const REVEALAGE_FORMAT: TextureFormat = TextureFormat::R16Float;
 
 struct TextureConfig {
     name: String,
@@ -40,17 +43,39 @@ impl Texture {
         }
     }
 
-    pub fn new_source(image: &ImageSlice, client: &Client) -> Self {
+    /// The gray slices are expanded to RGBA, like the color slices.
+    pub fn new_source(data: &SliceData, size: UVec2, client: &Client) -> Self {
         let cfg = TextureConfig {
             name: "Source".to_string(),
             usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
-            format: GRAY_FORMAT,
-            size: extent(UVec2::new(image.dim().0 as u32, image.dim().1 as u32)),
+            format: COLOR_FORMAT,
+            size: extent(size),
             multisampled: false,
             pad_bytes_per_row: false,
         };
         let texture = Self::new(cfg, client);
-        texture.send_image(image, &client.command_queue);
+        texture.send_image(data, &client.command_queue);
+
+        texture
+    }
+
+    /// RGBA image drawn over the slice.
+    pub fn new_overlay(bytes: &[u8], size: UVec2, client: &Client) -> Self {
+        let cfg = TextureConfig {
+            name: "Overlay".to_string(),
+            usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+            format: COLOR_FORMAT,
+            size: extent(size),
+            multisampled: false,
+            pad_bytes_per_row: false,
+        };
+        let texture = Self::new(cfg, client);
+        client.command_queue.write_texture(
+            texture.image_copy(),
+            bytes,
+            texture.data_layout(),
+            texture.inner.size(),
+        );
 
         texture
     }
@@ -79,6 +104,30 @@ impl Texture {
         Self::new(cfg, client)
     }
 
+    /// Render target of the weighted blended transparency. The multisampled texture is
+    /// resolved into the other one, which is read by the composite pass.
+    pub fn new_transparency(
+        name: &str,
+        format: TextureFormat,
+        multisampled: bool,
+        client: &Client,
+    ) -> Self {
+        let usage = if multisampled {
+            TextureUsages::RENDER_ATTACHMENT
+        } else {
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING
+        };
+        let cfg = TextureConfig {
+            name: name.to_string(),
+            usage,
+            format,
+            size: extent(client.img_size),
+            multisampled,
+            pad_bytes_per_row: false,
+        };
+        Self::new(cfg, client)
+    }
+
     pub fn new_target(client: &Client) -> Self {
         let cfg = TextureConfig {
             name: "Target".to_string(),
@@ -91,17 +140,23 @@ impl Texture {
         Self::new(cfg, client)
     }
 
-    fn send_image(&self, image: &ImageSlice, command_queue: &Queue) {
-        let bytes = image.as_slice_memory_order().unwrap();
+    fn send_image(&self, data: &SliceData, command_queue: &Queue) {
+        let bytes = match data {
+            SliceData::Gray(image) => {
+                let values = image.as_slice_memory_order().unwrap();
+                values.iter().flat_map(|&v| [v, v, v, 255]).collect()
+            }
+            SliceData::Color(image) => image.as_slice_memory_order().unwrap().to_vec(),
+        };
         command_queue.write_texture(
             self.image_copy(),
-            bytes,
+            &bytes,
             self.data_layout(),
             self.inner.size(),
         );
     }
 
-    pub fn image_copy(&self) -> ImageCopyTexture {
+    pub fn image_copy(&self) -> ImageCopyTexture<'_> {
         ImageCopyTexture {
             texture: &self.inner,
             mip_level: 0,
@@ -150,7 +205,7 @@ fn extent(size: UVec2) -> Extent3d {
 }
 
 fn pad_size(size: u32, align: u32) -> u32 {
-    ((size + align - 1) / align) * align
+    size.div_ceil(align) * align
 }
 
 impl Client {
@@ -0,0 +1,98 @@
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec2, Vec3};
+
+use crate::{graphics::Client, slicer::Slice};
+
+/// Hides the fibers farther than `half_width` from the slice plane.
+///
+/// The memory layout must match the WGSL struct of `uniforms.wgsl`, including its 16 bytes
+/// alignment.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct Slab {
+    /// Converts a voxel position to its distance along the slice axis, in mm
+    pub normal: Vec3,
+    /// Position of the slice along its axis, in mm
+    pub depth: f32,
+    /// Half of the slab width, in mm
+    pub half_width: f32,
+    _padding: [f32; 3],
+}
+
+impl Slab {
+    /// `width` is in mm. Without width, nothing is hidden.
+    pub fn new(slice: &Slice, width: Option<f32>, spacing: Vec3) -> Self {
+        let axis = slice.view.axis() as usize;
+        let mut normal = Vec3::ZERO;
+        normal[axis] = spacing[axis];
+
+        Self {
+            normal,
+            depth: slice.depth,
+            half_width: width.map_or(f32::MAX, |width| width / 2.),
+            _padding: [0.; 3],
+        }
+    }
+}
+
+/// Expands the fibers segments to screen-space ribbons, or their endpoints to disks.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct Ribbon {
+    /// Size of the output image, in pixels
+    pub viewport: Vec2,
+    /// Half of the ribbon width, or the disks radius, in pixels
+    pub half_width: f32,
+    /// 1 to shade the ribbons like tubes and the disks like spheres, 0 otherwise
+    pub shading: f32,
+}
+
+impl Ribbon {
+    pub fn new(client: &Client) -> Self {
+        Self {
+            viewport: client.img_size.as_vec2(),
+            half_width: client
+                .endpoint_sprites
+                .map_or(client.line_width, |sprites| sprites.size)
+                / 2.,
+            shading: client.shading as u32 as f32,
+        }
+    }
+}
+
+/// Light of the tubes and of the glyphs.
+#[derive(Clone, Copy)]
+pub struct Light {
+    /// Direction toward the light, in view space (x right, y up, z toward the viewer)
+    pub direction: Vec3,
+    pub ambient: f32,
+    pub specular: f32,
+}
+
+/// Blinn-Phong lighting of the tubes and of the glyphs, computed in view space.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct Lighting {
+    /// Rotation of the view, applied to the normals
+    pub rotation: Mat4,
+    /// Normalized direction toward the light, in view space
+    pub light: Vec3,
+    pub ambient: f32,
+    pub specular: f32,
+    pub shininess: f32,
+    _padding: [f32; 2],
+}
+
+impl Lighting {
+    /// `rotation` brings the normals to view space.
+    pub fn new(rotation: Mat4, light: &Light) -> Self {
+        Self {
+            rotation,
+            light: light.direction.normalize(),
+            ambient: light.ambient,
+            specular: light.specular,
+            shininess: 32.,
+            _padding: [0.; 2],
+        }
+    }
+}
@@ -1,18 +1,21 @@
 use std::mem::size_of;
 
 use bytemuck::{Pod, Zeroable};
-use glam::Vec2;
-use nalgebra::{Point3, Vector3};
-use wgpu::{vertex_attr_array, VertexAttribute, VertexBufferLayout};
+use glam::{Vec2, Vec3};
+use nalgebra::{Matrix3, Point3, Vector3};
+use wgpu::{vertex_attr_array, VertexAttribute, VertexBufferLayout, VertexStepMode};
 
 pub trait Vertex
 where
     Self: Sized + Copy + Clone + Pod + Zeroable,
 {
-    fn buffer_layout(attributes: &[VertexAttribute]) -> VertexBufferLayout {
+    /// Instance vertices are expanded in the vertex shader, using the vertex index.
+    const STEP_MODE: VertexStepMode = VertexStepMode::Vertex;
+
+    fn buffer_layout(attributes: &[VertexAttribute]) -> VertexBufferLayout<'_> {
         VertexBufferLayout {
             array_stride: size_of::<Self>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
+            step_mode: Self::STEP_MODE,
             attributes,
         }
     }
@@ -24,13 +27,14 @@ where
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
 pub struct ImageVertex {
-    pub canon: Vec2,
+    /// In the voxel space of the volume, like the fibers
+    pub position: Vec3,
     pub uv: Vec2,
 }
 
 impl Vertex for ImageVertex {
     fn attributes() -> Vec<wgpu::VertexAttribute> {
-        Vec::from(vertex_attr_array![0 => Float32x2, 1 => Float32x2])
+        Vec::from(vertex_attr_array![0 => Float32x3, 1 => Float32x2])
     }
 }
 
@@ -47,3 +51,89 @@ impl Vertex for FiberVertex {
         Vec::from(vertex_attr_array![0 => Float32x3, 1 => Float32x3])
     }
 }
+
+/// Vertex of a tube surface, with its outward normal in voxel space.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct TubeVertex {
+    pub position: Point3<f32>,
+    pub normal: Vector3<f32>,
+    pub color: Vector3<f32>,
+}
+
+impl Vertex for TubeVertex {
+    fn attributes() -> Vec<wgpu::VertexAttribute> {
+        Vec::from(vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x3])
+    }
+}
+
+/// Endpoint of a streamline, drawn as a disk instance.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct SpriteVertex {
+    pub center: Point3<f32>,
+    pub color: Vector3<f32>,
+}
+
+impl Vertex for SpriteVertex {
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
+
+    fn attributes() -> Vec<wgpu::VertexAttribute> {
+        Vec::from(vertex_attr_array![0 => Float32x3, 1 => Float32x3])
+    }
+}
+
+/// Two consecutive vertices of a streamline, drawn as a quad instance.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct SegmentVertex {
+    pub start: FiberVertex,
+    pub end: FiberVertex,
+}
+
+impl Vertex for SegmentVertex {
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
+
+    fn attributes() -> Vec<wgpu::VertexAttribute> {
+        Vec::from(vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+            2 => Float32x3,
+            3 => Float32x3
+        ])
+    }
+}
+
+/// Tensor glyph, expanded from a sphere in the vertex shader.
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+pub struct GlyphVertex {
+    /// In voxel space
+    pub center: Point3<f32>,
+    /// Converts the unit glyph to the voxel space
+    pub transform: Matrix3<f32>,
+    /// Inverse transpose of `transform`
+    pub normal_transform: Matrix3<f32>,
+    /// Superquadric exponents, and 1 if the symmetry axis is the principal direction, 0 if it
+    /// is the third one
+    pub shape: Vector3<f32>,
+    pub color: Vector3<f32>,
+}
+
+impl Vertex for GlyphVertex {
+    const STEP_MODE: VertexStepMode = VertexStepMode::Instance;
+
+    fn attributes() -> Vec<wgpu::VertexAttribute> {
+        Vec::from(vertex_attr_array![
+            0 => Float32x3,
+            1 => Float32x3,
+            2 => Float32x3,
+            3 => Float32x3,
+            4 => Float32x3,
+            5 => Float32x3,
+            6 => Float32x3,
+            7 => Float32x3,
+            8 => Float32x3
+        ])
+    }
+}
@@ -1,18 +1,24 @@
 use bytemuck::Pod;
-use glam::Mat3;
 use wgpu::{Buffer, CommandEncoder};
 
-use crate::graphics::{resources::quad_vertices, Context, Image, Slice};
+use crate::graphics::{
+    resources::{
+        quad_vertices,
+        uniform::{Lighting, Slab},
+        MAX_PLANES,
+    },
+    Context, Image, Slice,
+};
 
 mod render;
 mod transfer;
 
 impl Context {
-    pub fn execute_workloads(&self, slice: &Slice) -> Image {
+    pub fn execute_workloads(&self, slices: &[Slice]) -> Image {
         let mut command_encoder = self.command_encoder();
 
-        self.update_slice_data(slice);
-        self.render_slice(&slice.data, &mut command_encoder);
+        self.update_scene_data(slices);
+        self.render_scene(slices, &mut command_encoder);
         self.copy_target_to_buffer(&mut command_encoder);
 
         self.client.command_queue.submit([command_encoder.finish()]);
@@ -31,26 +37,38 @@ impl Context {
             })
     }
 
-    pub fn update_slice_data(&self, slice: &Slice) {
-        let vertices = quad_vertices(self.slice_transform(slice));
+    /// The first slice orients the scene, unless there is a camera.
+    pub fn update_scene_data(&self, slices: &[Slice]) {
+        let slice = slices.first().expect("The scene has at least one slice");
+        if slices.len() > MAX_PLANES {
+            panic!("A scene has at most {MAX_PLANES} slices.");
+        }
+        let vertices: Vec<_> = slices.iter().flat_map(quad_vertices).collect();
+        let rotation = self.parameters.view_rotation(slice);
 
+        // The slices and the tractogram share the same voxel space
         let transform = self.parameters.tractogram_projection
-            * slice.view.rotation() // Rotates the tractogram according to the view
+            * rotation // Rotates the scene according to the view
             * self.parameters.tractogram_alignment;
 
-        self.write(&self.res.image_vertices, vertices);
+        // A slab around one of the planes would cut the fibers near the others
+        let slab_width = self.client.slab.filter(|_| slices.len() == 1);
+        let slab = Slab::new(slice, slab_width, self.parameters.spacing);
+
+        self.write_slice(&self.res.image_vertices, &vertices);
         self.write(&self.res.transform, transform);
+        self.write(&self.res.slab, slab);
+        self.write(
+            &self.res.lighting,
+            Lighting::new(rotation, &self.client.light),
+        );
     }
 
-    fn slice_transform(&self, slice: &Slice) -> Mat3 {
-        let screen_space_scale =
-            self.parameters.fit_scale * slice.size().as_vec2() / self.client.img_size.as_vec2();
-
-        slice.view.orientation() * Mat3::from_scale(screen_space_scale)
+    fn write<T: Pod>(&self, buffer: &Buffer, data: T) {
+        self.write_slice(buffer, &[data]);
     }
 
-    fn write<T: Pod>(&self, buffer: &Buffer, data: T) {
-        let data = &[data];
+    fn write_slice<T: Pod>(&self, buffer: &Buffer, data: &[T]) {
         let bytes = bytemuck::cast_slice(data);
 
         self.client.command_queue.write_buffer(buffer, 0, bytes);
@@ -1,54 +1,254 @@
-use wgpu::{Color, CommandEncoder, Operations, RenderPassDepthStencilAttachment};
+use wgpu::{
+    BindGroup, Color, CommandEncoder, Operations, RenderPassDepthStencilAttachment, RenderPipeline,
+};
 
 use crate::{
     graphics::{
-        resources::{bind, Resources, Texture},
+        client::FiberStyle,
+        resources::{
+            bind, FiberBatch, Resources, Texture, TransparencyTargets, GLYPH_VERTEX_COUNT,
+        },
         Context,
     },
-    ImageSlice,
+    slicer::Slice,
 };
 
 impl Context {
-    pub(super) fn render_slice(&self, image: &ImageSlice, command_encoder: &mut CommandEncoder) {
-        let source_bind_group = bind::group::source(image, self);
-        let transform_bind_group;
+    pub(super) fn render_scene(&self, slices: &[Slice], command_encoder: &mut CommandEncoder) {
+        let overlay_pipeline = self.pipelines.overlay.as_ref();
+        let plane_bind_groups: Vec<_> = slices
+            .iter()
+            .map(|slice| {
+                let overlay = overlay_pipeline.map(|_| bind::group::overlay(slice, self));
+                (bind::group::source(slice, self), overlay)
+            })
+            .collect();
+        let transform_bind_group = bind::group::transform(self);
+        let lighting_bind_group = bind::group::lighting(self);
+        let glyphs = self.pipelines.glyph.as_ref().map(|pipeline| {
+            let field = self.res.tensor_field.as_ref();
+            let field = field.expect("Glyph pipeline is defined alongside the tensor field");
+            let instances: Vec<_> = slices
+                .iter()
+                .map(|slice| field.instances(slice, &self.client))
+                .collect();
+            (pipeline, instances)
+        });
+        let peaks = self.pipelines.peaks.as_ref().map(|pipeline| {
+            let field = self.res.peak_field.as_ref();
+            let field = field.expect("Peaks pipeline is defined alongside the peak field");
+            let vertices: Vec<_> = slices
+                .iter()
+                .map(|slice| field.vertices(slice, &self.client))
+                .collect();
+            (pipeline, vertices)
+        });
+        let odfs = self.pipelines.odf.as_ref().map(|pipeline| {
+            let field = self.res.odf_field.as_ref();
+            let field = field.expect("ODF pipeline is defined alongside the ODF field");
+            let meshes: Vec<_> = slices
+                .iter()
+                .map(|slice| field.meshes(slice, &self.client))
+                .collect();
+            (pipeline, meshes)
+        });
+        let points = self.pipelines.points.as_ref().map(|pipeline| {
+            let sprites = self.res.point_sprites.as_ref();
+            let sprites = sprites.expect("Points pipeline is defined alongside the point sprites");
+            (pipeline, sprites, bind::group::ribbon(self))
+        });
+        let slab_bind_group = (peaks.is_some() || odfs.is_some() || points.is_some())
+            .then(|| bind::group::slab(self));
+        let fiber_bind_groups = (!self.res.fibers.is_empty()).then(|| self.fiber_bind_groups());
+        let weighted = self.res.transparency_targets.is_some();
         {
-            let mut pass = render_pass(&self.res, self.client.white_mode, command_encoder);
-
-            // Resampling
-            pass.set_bind_group(0, &source_bind_group, &[]);
+            let mut pass =
+                render_pass(&self.res, self.client.white_mode, weighted, command_encoder);
 
-            pass.set_pipeline(&self.pipelines.resampling);
+            // Resampling, one quad per slice in the vertex buffer
+            pass.set_bind_group(1, &transform_bind_group, &[]);
             pass.set_vertex_buffer(0, self.res.image_vertices.slice(..));
-            pass.draw(0..6, 0..1);
+            for (plane, (source_bind_group, overlay_bind_group)) in
+                plane_bind_groups.iter().enumerate()
+            {
+                let vertices = 6 * plane as u32..6 * (plane as u32 + 1);
+                pass.set_bind_group(0, source_bind_group, &[]);
+                pass.set_pipeline(&self.pipelines.resampling);
+                pass.draw(vertices.clone(), 0..1);
 
-            // Streamline
-            if !self.res.fibers.is_empty() {
-                transform_bind_group = bind::group::transform(self);
+                // Density map, drawn over the slice with the same vertices
+                if let (Some(pipeline), Some(bind_group)) = (overlay_pipeline, overlay_bind_group) {
+                    pass.set_bind_group(0, bind_group, &[]);
+                    pass.set_pipeline(pipeline);
+                    pass.draw(vertices, 0..1);
+                }
+            }
+
+            // Tensor glyphs, over the slices
+            if let Some((pipeline, instances)) = &glyphs {
                 pass.set_bind_group(0, &transform_bind_group, &[]);
+                pass.set_bind_group(1, &lighting_bind_group, &[]);
+                pass.set_pipeline(pipeline);
+                for (instances, instance_count) in instances {
+                    pass.set_vertex_buffer(0, instances.slice(..));
+                    pass.draw(0..GLYPH_VERTEX_COUNT, 0..*instance_count);
+                }
+            }
 
-                let pipeline = &self
-                    .pipelines
-                    .streamline
-                    .as_ref()
-                    .expect("Streamline pipeline is defined alongside fibers resources");
+            // Peaks, over the slices
+            if let (Some((pipeline, vertices)), Some(slab_bind_group)) = (&peaks, &slab_bind_group)
+            {
+                pass.set_bind_group(0, &transform_bind_group, &[]);
+                pass.set_bind_group(1, slab_bind_group, &[]);
+                pass.set_pipeline(pipeline);
+                for (vertices, vertex_count) in vertices {
+                    pass.set_vertex_buffer(0, vertices.slice(..));
+                    pass.draw(0..*vertex_count, 0..1);
+                }
+            }
 
+            // ODF glyphs, over the slices
+            if let (Some((pipeline, meshes)), Some(slab_bind_group)) = (&odfs, &slab_bind_group) {
+                pass.set_bind_group(0, &transform_bind_group, &[]);
+                pass.set_bind_group(1, slab_bind_group, &[]);
+                pass.set_bind_group(2, &lighting_bind_group, &[]);
                 pass.set_pipeline(pipeline);
+                for (vertices, indices, index_count) in meshes {
+                    pass.set_vertex_buffer(0, vertices.slice(..));
+                    pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
+                    pass.draw_indexed(0..*index_count, 0, 0..1);
+                }
+            }
 
-                for fiber_batch in &self.res.fibers {
-                    pass.set_vertex_buffer(0, fiber_batch.vertices.slice(..));
-                    pass.set_index_buffer(fiber_batch.indices.slice(..), wgpu::IndexFormat::Uint32);
-                    pass.draw_indexed(0..fiber_batch.index_count, 0, 0..1);
+            // Point cloud, drawn as disks
+            if let (Some((pipeline, (instances, instance_count), ribbon)), Some(slab_bind_group)) =
+                (&points, &slab_bind_group)
+            {
+                pass.set_bind_group(0, &transform_bind_group, &[]);
+                pass.set_bind_group(1, slab_bind_group, &[]);
+                pass.set_bind_group(2, ribbon, &[]);
+                pass.set_pipeline(pipeline);
+                pass.set_vertex_buffer(0, instances.slice(..));
+                pass.draw(0..6, 0..*instance_count); // Two triangles per instance
+            }
+
+            // Streamline
+            if let Some(bind_groups) = &fiber_bind_groups {
+                self.set_fiber_bind_groups(&mut pass, &transform_bind_group, bind_groups);
+
+                // Drawn before the visible fibers, which must not be blended over
+                if let Some(pipeline) = &self.pipelines.streamline_behind {
+                    pass.set_blend_constant(blend_constant(self.client.behind_opacity));
+                    pass.set_pipeline(pipeline);
+                    self.draw_fibers(&mut pass);
+                }
+
+                // The weighted blended fibers are drawn in their own targets
+                if !weighted {
+                    pass.set_blend_constant(blend_constant(self.client.opacity));
+                    pass.set_pipeline(self.streamline_pipeline());
+                    self.draw_fibers(&mut pass);
                 }
             }
         }
+        if let (Some(targets), Some(bind_groups)) =
+            (&self.res.transparency_targets, &fiber_bind_groups)
+        {
+            {
+                let mut pass = transparency_pass(&self.res, targets, command_encoder);
+                self.set_fiber_bind_groups(&mut pass, &transform_bind_group, bind_groups);
+                pass.set_blend_constant(blend_constant(self.client.opacity));
+                pass.set_pipeline(self.streamline_pipeline());
+                self.draw_fibers(&mut pass);
+            }
+            self.composite(command_encoder);
+        }
         // TODO self.post_process()
     }
+
+    /// Slab and, depending on the fiber style, ribbon or lighting bind groups.
+    fn fiber_bind_groups(&self) -> (BindGroup, Option<BindGroup>) {
+        let style_bind_group = match self.client.fiber_style() {
+            FiberStyle::Lines => None,
+            FiberStyle::Ribbons | FiberStyle::Endpoints => Some(bind::group::ribbon(self)),
+            FiberStyle::Tubes => Some(bind::group::lighting(self)),
+        };
+        (bind::group::slab(self), style_bind_group)
+    }
+
+    fn set_fiber_bind_groups<'a>(
+        &self,
+        pass: &mut wgpu::RenderPass<'a>,
+        transform_bind_group: &'a BindGroup,
+        (slab_bind_group, style_bind_group): &'a (BindGroup, Option<BindGroup>),
+    ) {
+        pass.set_bind_group(0, transform_bind_group, &[]);
+        pass.set_bind_group(1, slab_bind_group, &[]);
+        if let Some(bind_group) = style_bind_group {
+            pass.set_bind_group(2, bind_group, &[]);
+        }
+    }
+
+    fn streamline_pipeline(&self) -> &RenderPipeline {
+        self.pipelines
+            .streamline
+            .as_ref()
+            .expect("Streamline pipeline is defined alongside fibers resources")
+    }
+
+    /// Blends the weighted blended fibers over the target texture.
+    fn composite(&self, command_encoder: &mut CommandEncoder) {
+        let composite_bind_group = bind::group::composite(self);
+        let pipeline = self
+            .pipelines
+            .composite
+            .as_ref()
+            .expect("Composite pipeline is defined alongside the transparency targets");
+
+        let color_attachment = wgpu::RenderPassColorAttachment {
+            view: &self.res.target_texture.view,
+            resolve_target: None,
+            ops: load(),
+        };
+        let mut pass = command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: label!("CompositeRenderPass"),
+            color_attachments: &[Some(color_attachment)],
+            ..Default::default()
+        });
+        pass.set_bind_group(0, &composite_bind_group, &[]);
+        pass.set_pipeline(pipeline);
+        pass.set_vertex_buffer(0, self.res.fullscreen_vertices.slice(..));
+        pass.draw(0..6, 0..1);
+    }
+
+    fn draw_fibers<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>) {
+        for fiber_batch in &self.res.fibers {
+            match fiber_batch {
+                FiberBatch::Mesh {
+                    vertices,
+                    indices,
+                    index_count,
+                } => {
+                    pass.set_vertex_buffer(0, vertices.slice(..));
+                    pass.set_index_buffer(indices.slice(..), wgpu::IndexFormat::Uint32);
+                    pass.draw_indexed(0..*index_count, 0, 0..1);
+                }
+                FiberBatch::Instances {
+                    instances,
+                    instance_count,
+                } => {
+                    pass.set_vertex_buffer(0, instances.slice(..));
+                    pass.draw(0..6, 0..*instance_count); // Two triangles per instance
+                }
+            }
+        }
+    }
 }
 
 fn render_pass<'a>(
     res: &'a Resources,
     white_mode: bool,
+    keep_depth: bool,
     command_encoder: &'a mut CommandEncoder,
 ) -> wgpu::RenderPass<'a> {
     let clear_color = if white_mode {
@@ -61,28 +261,83 @@ fn render_pass<'a>(
         resolve_target: Some(&res.target_texture.view),
         ops: clear(clear_color),
     };
+    let depth_store = if keep_depth {
+        wgpu::StoreOp::Store
+    } else {
+        wgpu::StoreOp::Discard
+    };
+    let depth_ops = Operations {
+        load: wgpu::LoadOp::Clear(1.),
+        store: depth_store,
+    };
     command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
         label: label!("RenderPass"),
         color_attachments: &[Some(color_attachment)],
-        depth_stencil_attachment: Some(depth_attachment(&res.depth_texture)),
+        depth_stencil_attachment: Some(depth_attachment(&res.depth_texture, depth_ops)),
+        ..Default::default()
+    })
+}
+
+/// Accumulates the transparent fibers, tested against the depth of the first pass.
+fn transparency_pass<'a>(
+    res: &'a Resources,
+    targets: &'a TransparencyTargets,
+    command_encoder: &'a mut CommandEncoder,
+) -> wgpu::RenderPass<'a> {
+    let accumulation = wgpu::RenderPassColorAttachment {
+        view: &targets.multisampled_accumulation.view,
+        resolve_target: Some(&targets.accumulation.view),
+        ops: clear(Color::TRANSPARENT),
+    };
+    let revealage = wgpu::RenderPassColorAttachment {
+        view: &targets.multisampled_revealage.view,
+        resolve_target: Some(&targets.revealage.view),
+        ops: clear(Color::WHITE),
+    };
+    let depth_ops = Operations {
+        load: wgpu::LoadOp::Load,
+        store: wgpu::StoreOp::Discard,
+    };
+    command_encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: label!("TransparencyRenderPass"),
+        color_attachments: &[Some(accumulation), Some(revealage)],
+        depth_stencil_attachment: Some(depth_attachment(&res.depth_texture, depth_ops)),
         ..Default::default()
     })
 }
 
-fn depth_attachment(texture: &Texture) -> RenderPassDepthStencilAttachment {
+fn depth_attachment(
+    texture: &Texture,
+    depth_ops: Operations<f32>,
+) -> RenderPassDepthStencilAttachment<'_> {
     RenderPassDepthStencilAttachment {
         view: &texture.view,
-        depth_ops: Some(Operations {
-            load: wgpu::LoadOp::Clear(1.),
-            store: wgpu::StoreOp::Discard,
-        }),
+        depth_ops: Some(depth_ops),
         stencil_ops: None,
     }
 }
 
+/// Same value for all channels.
+fn blend_constant(value: f32) -> Color {
+    let value = value as f64;
+    Color {
+        r: value,
+        g: value,
+        b: value,
+        a: value,
+    }
+}
+
 fn clear<T>(value: T) -> Operations<T> {
     Operations {
         load: wgpu::LoadOp::Clear(value),
         store: wgpu::StoreOp::Store,
     }
 }
+
+fn load<T>() -> Operations<T> {
+    Operations {
+        load: wgpu::LoadOp::Load,
+        store: wgpu::StoreOp::Store,
+    }
+}
@@ -1,19 +1,40 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
-use glam::{uvec2, uvec3, UVec2, UVec3};
-use nalgebra::Vector3;
+use glam::{uvec2, uvec3, vec3, UVec2, UVec3, Vec3};
+use nalgebra::{Matrix3, Point3, Vector3};
+use ndarray::{Array3, Array4, Axis};
 use nifti::NiftiHeader;
 use trk_io::Reader;
 
-use super::{file::fibers_reader, graphics::Coloring, slicer::View};
+use super::{
+    dropout::ReportFormat,
+    dti::DtiMap,
+    file::{fibers_reader, read_3d_image, read_4d_image, spacing, voxel_to_mm, world_to_voxel},
+    graphics::{
+        Arrows, Blending, Camera, Clustering, Coloring, Colormap, Density, DensityWeighting,
+        DirectionEncoding, EndpointColoring, EndpointSprites, Filter, GlyphShape, Light, Measure,
+        OdfField, PeakField, PointCloud, Roi, ShBasis, Subsampling, SubsamplingMethod, TensorField,
+        TensorOrder, Tube,
+    },
+    slicer::View,
+    turntable::{AnimationFormat, TurntableAxis},
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
-    /// Input NIfTI image
+    /// Input NIfTI image. RGB24 images, and 4D images with 3 components, are drawn in color
     pub input_image: PathBuf,
 
+    /// Use the absolute value of the components of a color input image, like a V1 map
+    #[arg(long)]
+    pub abs_colors: bool,
+
+    /// Scalar map, like the FA, multiplying the colors of a color input image
+    #[arg(long)]
+    pub color_weights: Option<PathBuf>,
+
     /// Use a white background instead of black
     #[arg(short, long, default_value = "false")]
     pub white: bool,
@@ -26,6 +47,71 @@ pub struct Args {
     #[arg(short, long, default_value = "50000", requires("fibers"))]
     pub batch_size: usize,
 
+    /// Maximum number of streamlines drawn, among the ones kept by the length and ROI filters
+    #[arg(long, requires("fibers"))]
+    pub max_streamlines: Option<usize>,
+
+    /// Fraction, in [0, 1], of the streamlines kept by the filters that are drawn
+    #[arg(long, default_value = "1.0", requires("fibers"))]
+    pub keep_fraction: f32,
+
+    /// How the drawn streamlines are selected
+    #[arg(long, default_value = "random", requires("fibers"))]
+    pub subsampling: SubsamplingMethod,
+
+    /// Seed of the random subsampling
+    #[arg(long, default_value = "0", requires("fibers"))]
+    pub seed: u64,
+
+    /// Draw the track density map over the slices instead of the fibers
+    #[arg(long, requires("fibers"))]
+    pub density: Option<DensityWeighting>,
+
+    /// Only draw the streamlines at least this long, in mm
+    #[arg(long, requires("fibers"))]
+    pub min_length: Option<f32>,
+
+    /// Only draw the streamlines at most this long, in mm
+    #[arg(long, requires("fibers"))]
+    pub max_length: Option<f32>,
+
+    /// NIfTI masks that the drawn streamlines must all reach
+    #[arg(long, num_args(1..), requires("fibers"))]
+    pub include: Vec<PathBuf>,
+
+    /// NIfTI masks that the drawn streamlines must not reach
+    #[arg(long, num_args(1..), requires("fibers"))]
+    pub exclude: Vec<PathBuf>,
+
+    /// Sphere, in RAS+ mm, that the drawn streamlines must reach. Can be repeated.
+    #[arg(
+        num_args(4),
+        long,
+        requires("fibers"),
+        allow_negative_numbers(true),
+        value_names = &["X", "Y", "Z", "RADIUS"]
+    )]
+    pub include_sphere: Vec<f32>,
+
+    /// Sphere, in RAS+ mm, that the drawn streamlines must not reach. Can be repeated.
+    #[arg(
+        num_args(4),
+        long,
+        requires("fibers"),
+        allow_negative_numbers(true),
+        value_names = &["X", "Y", "Z", "RADIUS"]
+    )]
+    pub exclude_sphere: Vec<f32>,
+
+    /// Only test the endpoints of the streamlines against the regions of interest
+    #[arg(long, requires("fibers"))]
+    pub endpoints: bool,
+
+    /// Remove the streamlines points that move them by less than this many pixels. With a
+    /// field of view, the pixels are measured through the center of the volume
+    #[arg(long, requires("fibers"))]
+    pub simplify: Option<f32>,
+
     /// Color mode for the fibers
     #[arg(short, long, default_value = "local", requires("fibers"))]
     pub coloring: ColoringInput,
@@ -40,6 +126,169 @@ pub struct Args {
     )]
     pub rgb: Vec<u32>,
 
+    /// Name of the TrackVis scalar used by the scalar coloring mode
+    #[arg(long, requires("coloring"), required_if_eq("coloring", "scalar"))]
+    pub scalar: Option<String>,
+
+    /// Name of the TrackVis property used by the property coloring mode
+    #[arg(long, requires("coloring"), required_if_eq("coloring", "property"))]
+    pub property: Option<String>,
+
+    /// Colormap used by the scalar, property, length, curvature, torsion and position coloring
+    /// modes, and by the density map
+    #[arg(long, default_value = "viridis", requires("fibers"))]
+    pub colormap: Colormap,
+
+    /// Values mapped to the ends of the colormap. Defaults to the range of the values.
+    #[arg(
+        num_args(2),
+        long,
+        requires("fibers"),
+        allow_negative_numbers(true),
+        value_names = &["MIN", "MAX"]
+    )]
+    pub limits: Vec<f32>,
+
+    /// Maximum distance, in mm, between a streamline and the centroid of its cluster, used by
+    /// the cluster coloring mode [default: 10.0]
+    #[arg(long)]
+    pub cluster_threshold: Option<f32>,
+
+    /// Number of points of the streamlines resampled by the cluster coloring mode
+    /// [default: 12]
+    #[arg(long)]
+    pub cluster_points: Option<usize>,
+
+    /// Only draw the centroid of each cluster, with the cluster coloring mode
+    #[arg(long)]
+    pub centroids_only: bool,
+
+    /// Saturation of the local and endpoint coloring modes. 0 is grayscale.
+    #[arg(long, default_value = "1.0", requires("fibers"))]
+    pub saturation: f32,
+
+    /// Gamma, positive, of the local and endpoint coloring modes. Values above 1 brighten the
+    /// colors.
+    #[arg(long, default_value = "1.0", requires("fibers"))]
+    pub gamma: f32,
+
+    /// NIfTI map in [0, 1], like a FA map, multiplying the fibers colors
+    #[arg(long, requires("fibers"))]
+    pub modulation: Option<PathBuf>,
+
+    /// Only show the fibers within a slab of this width (mm) centered on the slice
+    #[arg(long, requires("fibers"))]
+    pub slab: Option<f32>,
+
+    /// Opacity, in [0, 1], of the fibers behind the slice. They are hidden by default.
+    #[arg(long, default_value = "0.0", requires("fibers"))]
+    pub behind_opacity: f32,
+
+    /// Width of the fibers, in pixels
+    #[arg(long, default_value = "1.0", requires("fibers"))]
+    pub line_width: f32,
+
+    /// Shade the fibers like tubes, or the endpoint sprites like spheres. Requires a line
+    /// width larger than 1 or endpoint sprites.
+    #[arg(long, requires("fibers"))]
+    pub shading: bool,
+
+    /// Draw arrowheads along the fibers, spaced by this arc length in mm, pointing toward
+    /// their last point
+    #[arg(long, requires("fibers"))]
+    pub arrows: Option<f32>,
+
+    /// Length of the arrowheads, in mm
+    #[arg(long, default_value = "2.0", requires("arrows"))]
+    pub arrow_size: f32,
+
+    /// Only draw the endpoints of the fibers, as disks of this diameter in pixels
+    #[arg(long, requires("fibers"))]
+    pub endpoint_sprites: Option<f32>,
+
+    /// Color of the endpoint sprites
+    #[arg(long, default_value = "end", requires("endpoint_sprites"))]
+    pub endpoint_coloring: EndpointColoring,
+
+    /// Draw the fibers as lit 3D tubes of this radius, in mm
+    #[arg(long, requires("fibers"))]
+    pub tube_radius: Option<f32>,
+
+    /// Number of sides of the tubes
+    #[arg(long, default_value = "8", requires("tube_radius"))]
+    pub tube_sides: u32,
+
+    /// Direction toward the light lighting the tubes and the glyphs, in view space (x right,
+    /// y up, z toward the viewer)
+    #[arg(
+        num_args(3),
+        long,
+        default_values = ["0.3", "0.5", "1.0"],
+        allow_negative_numbers(true),
+        value_names = &["X", "Y", "Z"]
+    )]
+    pub light: Vec<f32>,
+
+    /// Ambient term of the tubes and glyphs lighting, in [0, 1]
+    #[arg(long, default_value = "0.3")]
+    pub ambient: f32,
+
+    /// Specular term of the tubes and glyphs lighting
+    #[arg(long, default_value = "0.4")]
+    pub specular: f32,
+
+    /// Opacity, in [0, 1], of the fibers in front of the slice, or of the density map
+    #[arg(long, default_value = "1.0", requires("fibers"))]
+    pub opacity: f32,
+
+    /// How the fibers are combined when they are transparent, with an opacity below 1
+    #[arg(long, default_value = "weighted", requires("fibers"))]
+    pub blending: Blending,
+
+    /// NIfTI volume of diffusion tensors, with 6 components, drawn as glyphs over the slices
+    #[arg(long)]
+    pub tensor: Option<PathBuf>,
+
+    /// Order of the tensor components
+    #[arg(long, default_value = "fsl", requires("tensor"))]
+    pub tensor_order: TensorOrder,
+
+    /// Shape of the tensor glyphs
+    #[arg(long, default_value = "superquadric", requires("tensor"))]
+    pub glyph: GlyphShape,
+
+    /// Size of the tensor glyphs, relative to the voxel size
+    #[arg(long, default_value = "0.9", requires("tensor"))]
+    pub glyph_scale: f32,
+
+    /// NIfTI volume of peak directions, with 3 components per peak, drawn as lines over the slices
+    #[arg(long)]
+    pub peaks: Option<PathBuf>,
+
+    /// Peaks with a smaller amplitude are hidden
+    #[arg(long, default_value = "0.0", requires("peaks"))]
+    pub peaks_threshold: f32,
+
+    /// Length of the largest peaks, relative to the voxel size
+    #[arg(long, default_value = "0.9", requires("peaks"))]
+    pub peaks_scale: f32,
+
+    /// NIfTI volume of spherical harmonics coefficients, drawn as ODF glyphs over the slices
+    #[arg(long)]
+    pub odf: Option<PathBuf>,
+
+    /// Basis of the spherical harmonics coefficients
+    #[arg(long, default_value = "mrtrix", requires("odf"))]
+    pub sh_basis: ShBasis,
+
+    /// Maximum order of the spherical harmonics, which defaults to the order of the image
+    #[arg(long, requires("odf"))]
+    pub sh_order: Option<usize>,
+
+    /// Size of the ODF glyphs, relative to the voxel size
+    #[arg(long, default_value = "0.9", requires("odf"))]
+    pub odf_scale: f32,
+
     /// Output folder to save all png
     pub output: PathBuf,
 
@@ -52,9 +301,114 @@ pub struct Args {
     )]
     pub output_size: Vec<u32>,
 
+    /// Azimuth of the camera, in degrees, around the superior axis: 0 looks from posterior,
+    /// 90 from the left and 180 from anterior. Replaces the orientation of the views
+    #[arg(long, allow_negative_numbers(true))]
+    pub azimuth: Option<f32>,
+
+    /// Elevation of the camera, in degrees, above the axial plane: 90 looks from superior
+    #[arg(long, allow_negative_numbers(true))]
+    pub elevation: Option<f32>,
+
+    /// Magnification of the scene
+    #[arg(long, default_value = "1.0")]
+    pub zoom: f32,
+
+    /// Use a perspective projection with this vertical field of view, in degrees, instead of
+    /// the orthographic one. The specular highlights still assume a distant viewer
+    #[arg(long)]
+    pub field_of_view: Option<f32>,
+
+    /// Draw the sagittal, coronal and axial slices through this point, in mm, in a single 3D
+    /// scene instead of the views. The camera looks from the left, anterior and superior side
+    /// by default
+    #[arg(
+        num_args(3),
+        long,
+        allow_negative_numbers(true),
+        value_names = &["X", "Y", "Z"]
+    )]
+    pub crosshair: Vec<f32>,
+
     /// Which view(s) to use tp capture the image(s)
     #[arg(num_args(1..7), long, default_values = ["superior", "posterior", "left"])]
     pub views: Vec<View>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    /// Fit diffusion tensors to the input image, a DWI, and slice one of their maps
+    Dti {
+        /// FSL b-values of the DWI volumes
+        #[arg(long)]
+        bval: PathBuf,
+
+        /// FSL b-vectors of the DWI volumes
+        #[arg(long)]
+        bvec: PathBuf,
+
+        /// NIfTI mask of the fitted voxels. Defaults to the voxels with a positive b=0 signal
+        #[arg(long)]
+        mask: Option<PathBuf>,
+
+        /// Map sliced instead of the DWI
+        #[arg(long, default_value = "rgb")]
+        map: DtiMap,
+    },
+    /// Draw the directions of the gradient table of the input image, a DWI, on spheres
+    Gradients {
+        /// FSL b-values of the DWI volumes
+        #[arg(long, requires("bvec"), required_unless_present("grad"))]
+        bval: Option<PathBuf>,
+
+        /// FSL b-vectors of the DWI volumes
+        #[arg(long, requires("bval"))]
+        bvec: Option<PathBuf>,
+
+        /// MRtrix gradient table of the DWI volumes
+        #[arg(long, conflicts_with("bval"))]
+        grad: Option<PathBuf>,
+
+        /// Diameter of the points, in pixels
+        #[arg(long, default_value = "12")]
+        point_size: f32,
+    },
+    /// Detect the signal dropouts in the axial slices of the input image, a DWI
+    Dropout {
+        /// Slices whose robust z-score is below minus this threshold are reported
+        #[arg(long, default_value = "3.0")]
+        threshold: f32,
+
+        /// Number of the worst slices saved as images
+        #[arg(long, default_value = "5")]
+        worst: usize,
+
+        /// Format of the report of the dropouts
+        #[arg(long, default_value = "csv")]
+        format: ReportFormat,
+    },
+    /// Render an animation of the middle slice of each view, with the fibers, turning around
+    /// an axis. The camera options give the first frame
+    Turntable {
+        /// Axis of the rotation
+        #[arg(long, default_value = "superior")]
+        axis: TurntableAxis,
+
+        /// Number of frames of a full turn
+        #[arg(long, default_value = "36")]
+        frames: u32,
+
+        /// Frames per second of the animation
+        #[arg(long, default_value = "12")]
+        fps: u16,
+
+        /// Format of the animation
+        #[arg(long, default_value = "gif")]
+        format: AnimationFormat,
+    },
 }
 
 #[derive(Clone, Debug, clap::ValueEnum)]
@@ -62,6 +416,13 @@ pub enum ColoringInput {
     Local,
     Endpoint,
     Uniform,
+    Scalar,
+    Property,
+    Length,
+    Curvature,
+    Torsion,
+    Position,
+    Cluster,
 }
 
 pub struct ContextInputs {
@@ -69,8 +430,36 @@ pub struct ContextInputs {
     pub size_3d: UVec3,
     pub dst_img_size: UVec2,
     pub streamline_batch_size: usize,
+    pub subsampling: Subsampling,
+    pub filter: Filter,
+    pub simplify: Option<f32>,
+    pub density: Option<Density>,
     pub white_mode: bool,
     pub coloring: Coloring,
+    pub voxel_to_mm: Matrix3<f32>,
+    pub mm_to_voxel: Matrix3<f32>,
+    pub modulation: Option<Array3<f32>>,
+    pub tensor_field: Option<TensorField>,
+    pub peak_field: Option<PeakField>,
+    pub odf_field: Option<OdfField>,
+    /// Drawn as disks, with the size of the endpoint sprites
+    pub points: Option<PointCloud>,
+    pub spacing: Vec3,
+    pub slab: Option<f32>,
+    pub behind_opacity: f32,
+    pub line_width: f32,
+    pub shading: bool,
+    pub tube: Option<Tube>,
+    pub light: Light,
+    pub endpoint_sprites: Option<EndpointSprites>,
+    pub arrows: Option<Arrows>,
+    pub opacity: f32,
+    pub blending: Blending,
+    /// Free camera, the views orient the scene otherwise
+    pub camera: Option<Camera>,
+    pub zoom: f32,
+    /// Vertical field of view of the perspective projection, in degrees
+    pub field_of_view: Option<f32>,
 }
 
 impl ContextInputs {
@@ -80,30 +469,291 @@ impl ContextInputs {
             .as_ref()
             .map(|path| fibers_reader(path, nifti_header));
 
-        let coloring = match args.coloring {
-            ColoringInput::Local => Coloring::Local,
-            ColoringInput::Endpoint => Coloring::Endpoint,
-            ColoringInput::Uniform => {
-                Coloring::Uniform(Vector3::new(args.rgb[0], args.rgb[1], args.rgb[2]))
-            }
+        if args.shading && args.line_width <= 1. && args.endpoint_sprites.is_none() {
+            panic!("The shading requires a line width larger than 1 or endpoint sprites.");
+        }
+        if args.arrows.is_some_and(|spacing| spacing <= 0.) || args.arrow_size <= 0. {
+            panic!("The spacing and the size of the arrowheads must be positive.");
+        }
+        if args.zoom <= 0. {
+            panic!("The zoom must be positive.");
+        }
+        let not_positive = |value: Option<f32>| value.is_some_and(|value| value <= 0.);
+        if not_positive(args.slab) || not_positive(args.tube_radius) || args.line_width <= 0. {
+            panic!("The slab width, the tube radius and the line width must be positive.");
+        }
+        if not_positive(args.endpoint_sprites) {
+            panic!("The size of the endpoint sprites must be positive.");
+        }
+        if args.simplify.is_some_and(|pixels| pixels < 0.) {
+            panic!("The simplification tolerance can't be negative.");
+        }
+        if !args.limits.is_empty() && args.limits[0] >= args.limits[1] {
+            panic!("The minimum of the limits must be smaller than their maximum.");
+        }
+        if args.light.iter().all(|&c| c == 0.) || args.light.iter().any(|c| !c.is_finite()) {
+            panic!("The light direction must be a finite and non-zero vector.");
+        }
+
+        let voxel_to_mm = voxel_to_mm(nifti_header);
+        let Some(mm_to_voxel) = voxel_to_mm.try_inverse() else {
+            panic!("The affine of the input image must be invertible.");
         };
+        let coloring = coloring(args, fibers_reader.as_ref(), voxel_to_mm);
+        let size_3d = get_dim(nifti_header);
+
+        let modulation = args.modulation.as_ref().map(|path| {
+            let (header, map) = read_3d_image(path);
+            if get_dim(&header) != size_3d {
+                panic!("The modulation map must have the same dimensions as the input image.");
+            }
+            map
+        });
+
+        let tensor_field = args.tensor.as_ref().map(|path| {
+            let (header, values) = read_4d_field(path, size_3d, "tensor");
+            TensorField::new(
+                values,
+                args.tensor_order,
+                args.glyph,
+                args.glyph_scale,
+                voxel_to_mm,
+                mm_to_voxel,
+                spacing(&header),
+            )
+        });
+
+        let peak_field = args.peaks.as_ref().map(|path| {
+            let (header, values) = read_4d_field(path, size_3d, "peaks");
+            PeakField::new(
+                values,
+                args.peaks_threshold,
+                args.peaks_scale,
+                mm_to_voxel,
+                spacing(&header),
+            )
+        });
+
+        let odf_field = args.odf.as_ref().map(|path| {
+            let (header, values) = read_4d_field(path, size_3d, "ODF");
+            OdfField::new(
+                values,
+                args.sh_basis,
+                args.sh_order,
+                args.odf_scale,
+                mm_to_voxel,
+                spacing(&header),
+            )
+        });
+
         ContextInputs {
             fibers_reader,
-            size_3d: get_dim(nifti_header),
+            size_3d,
             dst_img_size: uvec2(args.output_size[0], args.output_size[1]),
             streamline_batch_size: args.batch_size,
+            subsampling: Subsampling {
+                max_streamlines: args.max_streamlines,
+                keep_fraction: args.keep_fraction.clamp(0., 1.),
+                method: args.subsampling,
+                seed: args.seed,
+            },
+            filter: filter(args, nifti_header, &mm_to_voxel),
+            simplify: args.simplify,
+            density: args.density.map(|weighting| Density {
+                weighting,
+                colormap: args.colormap,
+                limits: (!args.limits.is_empty()).then(|| (args.limits[0], args.limits[1])),
+                size_3d,
+            }),
             white_mode: args.white,
             coloring,
+            voxel_to_mm,
+            mm_to_voxel,
+            modulation,
+            tensor_field,
+            peak_field,
+            odf_field,
+            points: None,
+            spacing: Vec3::from(<[f32; 3]>::from(spacing(nifti_header))),
+            slab: args.slab,
+            behind_opacity: args.behind_opacity.clamp(0., 1.),
+            line_width: args.line_width,
+            shading: args.shading,
+            tube: args.tube_radius.map(|radius| Tube {
+                radius,
+                sides: args.tube_sides.max(3),
+            }),
+            light: Light {
+                direction: vec3(args.light[0], args.light[1], args.light[2]),
+                ambient: args.ambient,
+                specular: args.specular,
+            },
+            endpoint_sprites: args.endpoint_sprites.map(|size| EndpointSprites {
+                size,
+                coloring: args.endpoint_coloring,
+            }),
+            arrows: args.arrows.map(|spacing| Arrows {
+                spacing,
+                size: args.arrow_size,
+            }),
+            opacity: args.opacity.clamp(0., 1.),
+            blending: args.blending,
+            camera: camera(args),
+            zoom: args.zoom,
+            field_of_view: args.field_of_view,
         }
     }
 }
 
+/// Applies the absolute value and the weights of the options to the colors of the input image.
+pub fn colors(args: &Args, mut data: Array4<f32>) -> Array4<f32> {
+    if args.abs_colors {
+        data.mapv_inplace(f32::abs);
+    }
+    if let Some(path) = &args.color_weights {
+        let (_, weights) = read_3d_image::<_, f32>(path);
+        if weights.dim() != data.index_axis(Axis(3), 0).dim() {
+            panic!("The color weights must have the same dimensions as the input image.");
+        }
+        for mut channel in data.axis_iter_mut(Axis(3)) {
+            channel *= &weights;
+        }
+    }
+    data
+}
+
+/// Reads a 4D image drawn over the slices, which must be in the grid of the input image.
+fn read_4d_field(path: &Path, size_3d: UVec3, name: &str) -> (NiftiHeader, Array4<f32>) {
+    let (header, values) = read_4d_image(path);
+    let (x, y, z, _) = values.dim();
+    if uvec3(x as u32, y as u32, z as u32) != size_3d {
+        panic!("The {name} image must have the same dimensions as the input image.");
+    }
+    (header, values)
+}
+
+/// Voxel of the crosshair, clamped to the image.
+pub fn crosshair(
+    args: &Args,
+    nifti_header: &NiftiHeader,
+    mm_to_voxel: &Matrix3<f32>,
+) -> Option<[usize; 3]> {
+    if args.crosshair.is_empty() {
+        return None;
+    }
+    let position = Point3::new(args.crosshair[0], args.crosshair[1], args.crosshair[2]);
+    let voxel = world_to_voxel(nifti_header, mm_to_voxel, position);
+    let size_3d = get_dim(nifti_header);
+    Some([0, 1, 2].map(|i| (voxel[i].floor().max(0.) as usize).min(size_3d[i] as usize - 1)))
+}
+
+/// The turntable needs the depth range of a free camera, and the crosshair a 3D view.
+fn camera(args: &Args) -> Option<Camera> {
+    let crosshair = !args.crosshair.is_empty();
+    let turntable = matches!(args.command, Some(Command::Turntable { .. }));
+    let (azimuth, elevation) = if crosshair { (135., 30.) } else { (0., 0.) };
+    (args.azimuth.is_some() || args.elevation.is_some() || crosshair || turntable).then(|| Camera {
+        azimuth: args.azimuth.unwrap_or(azimuth),
+        elevation: args.elevation.unwrap_or(elevation),
+    })
+}
+
+fn filter(args: &Args, nifti_header: &NiftiHeader, mm_to_voxel: &Matrix3<f32>) -> Filter {
+    let size_3d = get_dim(nifti_header);
+    let mask = |path: &PathBuf| {
+        let (header, mask) = read_3d_image::<_, f32>(path);
+        if get_dim(&header) != size_3d {
+            panic!("The ROI {path:?} must have the same dimensions as the input image.");
+        }
+        Roi::Mask(mask.mapv(|v| v > 0.))
+    };
+    let spheres = |values: &[f32]| -> Vec<Roi> {
+        values
+            .chunks_exact(4)
+            .map(|v| Roi::Sphere {
+                center: world_to_voxel(nifti_header, mm_to_voxel, Point3::new(v[0], v[1], v[2])),
+                radius: v[3],
+            })
+            .collect()
+    };
+
+    let mut include: Vec<Roi> = args.include.iter().map(mask).collect();
+    include.extend(spheres(&args.include_sphere));
+    let mut exclude: Vec<Roi> = args.exclude.iter().map(mask).collect();
+    exclude.extend(spheres(&args.exclude_sphere));
+
+    Filter {
+        min_length: args.min_length,
+        max_length: args.max_length,
+        include,
+        exclude,
+        endpoints: args.endpoints,
+    }
+}
+
+fn coloring(args: &Args, fibers_reader: Option<&Reader>, voxel_to_mm: Matrix3<f32>) -> Coloring {
+    let header = || &fibers_reader.expect("Coloring requires fibers").header;
+    let mapped = |measure| {
+        let limits = (!args.limits.is_empty()).then(|| (args.limits[0], args.limits[1]));
+        Coloring::Mapped(measure, args.colormap, limits)
+    };
+
+    if args.gamma <= 0. {
+        panic!("The gamma must be positive.");
+    }
+    let cluster_options =
+        args.cluster_threshold.is_some() || args.cluster_points.is_some() || args.centroids_only;
+    if cluster_options && !matches!(args.coloring, ColoringInput::Cluster) {
+        panic!("The cluster options require the cluster coloring mode.");
+    }
+    let encoding = DirectionEncoding {
+        voxel_to_mm,
+        saturation: args.saturation,
+        gamma: args.gamma,
+    };
+
+    match args.coloring {
+        ColoringInput::Local => Coloring::Local(encoding),
+        ColoringInput::Endpoint => Coloring::Endpoint(encoding),
+        ColoringInput::Uniform => {
+            Coloring::Uniform(Vector3::new(args.rgb[0], args.rgb[1], args.rgb[2]))
+        }
+        ColoringInput::Scalar => {
+            let name = args.scalar.as_deref().expect("Required by clap");
+            let index = find_name(&header().scalars_name, name);
+            mapped(Measure::Scalar(index))
+        }
+        ColoringInput::Property => {
+            let name = args.property.as_deref().expect("Required by clap");
+            let index = find_name(&header().properties_name, name);
+            mapped(Measure::Property(index))
+        }
+        ColoringInput::Length => mapped(Measure::Length),
+        ColoringInput::Curvature => mapped(Measure::Curvature),
+        ColoringInput::Torsion => mapped(Measure::Torsion),
+        ColoringInput::Position => mapped(Measure::Position),
+        ColoringInput::Cluster => Coloring::Cluster(Clustering {
+            threshold: args.cluster_threshold.unwrap_or(10.),
+            nb_points: args.cluster_points.unwrap_or(12),
+            centroids_only: args.centroids_only,
+        }),
+    }
+}
+
+fn find_name(names: &[String], name: &str) -> usize {
+    names
+        .iter()
+        .position(|n| n == name)
+        .unwrap_or_else(|| panic!("{name:?} isn't in the TrackVis file. Available: {names:?}"))
+}
+
+/// Spatial dimensions of the image. The components of the color images are ignored.
 fn get_dim(nifti_header: &NiftiHeader) -> UVec3 {
     let dim: &[u16] = nifti_header
         .dim()
         .expect("The NIfTI must have consistent dimensions");
 
-    if dim.len() != 3 {
+    if dim.len() != 3 && dim.len() != 4 {
         panic!("A 3D image is expected.")
     }
     uvec3(dim[0] as u32, dim[1] as u32, dim[2] as u32)
@@ -2,14 +2,22 @@ use std::time::Instant;
 
 use clap::Parser;
 
-use file::read_3d_image;
-use inputs::{Args, ContextInputs};
-use slicer::{ImageSlice, Slicer};
+use dti::TensorMaps;
+use file::{
+    read_3d_image, read_4d_image, read_fsl_gradients, read_mrtrix_gradients, read_volume, Volume,
+};
+use inputs::{Args, Command, ContextInputs};
+use nifti::NiftiHeader;
+use slicer::{Slice, Slicer};
 
+mod dropout;
+mod dti;
 mod file;
+mod gradient_plot;
 mod graphics;
 mod inputs;
 mod slicer;
+mod turntable;
 
 type Image = image::RgbaImage;
 
@@ -19,19 +27,114 @@ fn main() {
     init_logger();
 
     let args = Args::parse();
-    let (nifti_header, data) = read_3d_image::<_, f32>(&args.input_image);
+    let (nifti_header, volume) = match &args.command {
+        None | Some(Command::Turntable { .. }) => read_volume(&args.input_image),
+        Some(Command::Gradients {
+            bval,
+            bvec,
+            grad,
+            point_size,
+        }) => {
+            let gradients = match (bval, bvec, grad) {
+                (Some(bval), Some(bvec), _) => read_fsl_gradients(bval, bvec),
+                (_, _, Some(grad)) => read_mrtrix_gradients(grad),
+                _ => unreachable!("Required by clap"),
+            };
+            let header = NiftiHeader::from_file(&args.input_image)
+                .expect("NIfTI file has a valid and readable format.");
+            if header.dim[0] != 4 || header.dim[4] as usize != gradients.bvals.len() {
+                panic!("The input image must have one volume per gradient.");
+            }
+            gradient_plot::render(&args, &gradients, *point_size);
+
+            log::info!("Program duration: {:?}", start.elapsed());
+            return;
+        }
+        Some(Command::Dropout {
+            threshold,
+            worst,
+            format,
+        }) => {
+            let (header, dwi) = read_4d_image::<_, f32>(&args.input_image);
+            dropout::run(&args, header, &dwi, *threshold, *worst, *format);
+
+            log::info!("Program duration: {:?}", start.elapsed());
+            return;
+        }
+        Some(Command::Dti {
+            bval,
+            bvec,
+            mask,
+            map,
+        }) => {
+            let (header, dwi) = read_4d_image::<_, f32>(&args.input_image);
+            let gradients = read_fsl_gradients(bval, bvec);
+            let mask = mask
+                .as_ref()
+                .map(|path| read_3d_image::<_, f32>(path).1.mapv(|v| v > 0.));
+            let maps = TensorMaps::fit(&dwi, &gradients, mask.as_ref());
+            (header, maps.volume(*map))
+        }
+    };
 
     let inputs = ContextInputs::new(&args, &nifti_header);
+    let crosshair = inputs::crosshair(&args, &nifti_header, &inputs.mm_to_voxel);
     let mut graphics = graphics::Context::new(inputs);
 
-    let slicer = Slicer::from_3d(nifti_header, data, 3, &args.views, (0.3, 0.7));
+    // One animation per view
+    let nb_slices = match args.command {
+        Some(Command::Turntable { .. }) => 1,
+        _ => 3,
+    };
+    let slicer = match (volume, crosshair) {
+        (Volume::Scalar(data), Some(crosshair)) => {
+            Slicer::from_3d_crosshair(nifti_header, data, crosshair)
+        }
+        (Volume::Scalar(data), None) => {
+            Slicer::from_3d(nifti_header, data, nb_slices, &args.views, (0.3, 0.7))
+        }
+        (Volume::Color(data), crosshair) => {
+            let data = inputs::colors(&args, data);
+            match crosshair {
+                Some(crosshair) => Slicer::from_color_crosshair(nifti_header, data, crosshair),
+                None => Slicer::from_color(nifti_header, data, nb_slices, &args.views, (0.3, 0.7)),
+            }
+        }
+    };
+    // The slices through the crosshair are drawn together
+    let scenes: Vec<&[Slice]> = match crosshair {
+        Some(_) => vec![&slicer.slices],
+        None => slicer.slices.chunks(1).collect(),
+    };
+
+    if let Some(Command::Turntable {
+        axis,
+        frames,
+        fps,
+        format,
+    }) = args.command
+    {
+        for scene in scenes {
+            turntable::render(
+                &args,
+                &mut graphics,
+                scene,
+                axis,
+                frames.max(1),
+                format,
+                fps.max(1),
+            );
+        }
+        log::info!("Program duration: {:?}", start.elapsed());
+        return;
+    }
 
-    for slice in slicer.slices {
-        let image = graphics.process_slice(&slice);
+    for scene in scenes {
+        let image = graphics.process_scene(scene);
 
         // TODO Support a prefix, like "prefix{}_{}.png"
         let mut write_to = args.output.clone();
-        write_to.push(format!("{}_{}.png", slice.view.name(), slice.index));
+        write_to.push(format!("{}.png", slicer::scene_name(scene)));
         file::save_image(image, &write_to);
     }
 
@@ -3,13 +3,18 @@ use std::{
     str::FromStr,
 };
 
-use glam::{uvec2, vec2, Mat3, Mat4, UVec2};
-use ndarray::{Array2, Array3, ShapeBuilder};
+use glam::{uvec2, Mat4, UVec2};
+use ndarray::{s, Array2, Array3, Array4, ShapeBuilder};
 use nifti::NiftiHeader;
 
 pub type ImageSlice = Array2<u8>;
+/// RGBA bytes, `(width, height, 4)`
+pub type ColorSlice = Array3<u8>;
 pub type Spacing = (f32, f32, f32);
 
+/// Views of the sagittal, coronal and axial slices through a crosshair.
+const CROSSHAIR_VIEWS: [View; 3] = [View::Left, View::Posterior, View::Superior];
+
 #[derive(Copy, Clone, Debug)]
 pub enum Axis {
     Sagittal = 0,
@@ -17,6 +22,17 @@ pub enum Axis {
     Axial,
 }
 
+impl Axis {
+    /// Returns the volume axes along the width and the height of the slices.
+    pub fn in_plane(&self) -> (usize, usize) {
+        match self {
+            Axis::Sagittal => (1, 2),
+            Axis::Coronal => (0, 2),
+            Axis::Axial => (0, 1),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, clap::ValueEnum)]
 pub enum View {
     Left,
@@ -38,14 +54,6 @@ impl View {
             View::Inferior => Mat4::from_rotation_y(PI),
         }
     }
-
-    pub fn orientation(&self) -> Mat3 {
-        let x = match self {
-            View::Left | View::Anterior | View::Inferior => 1.,
-            View::Right | View::Posterior | View::Superior => -1.,
-        };
-        Mat3::from_scale(vec2(x, 1.))
-    }
 }
 
 impl FromStr for View {
@@ -85,16 +93,25 @@ impl View {
     }
 }
 
+pub enum SliceData {
+    Gray(ImageSlice),
+    Color(ColorSlice),
+}
+
 pub struct Slice {
-    pub data: ImageSlice,
+    pub data: SliceData,
     pub view: View,
     pub index: usize,
-    pub _depth: f32,
+    /// Position of the slice center along its axis, in mm
+    pub depth: f32,
 }
 
 impl Slice {
     pub fn size(&self) -> UVec2 {
-        let (width, height) = self.data.dim();
+        let (width, height) = match &self.data {
+            SliceData::Gray(data) => data.dim(),
+            SliceData::Color(data) => (data.dim().0, data.dim().1),
+        };
         uvec2(width as u32, height as u32)
     }
 }
@@ -112,6 +129,34 @@ impl Slicer {
         nb_slices: usize,
         views: &[View],
         range: (f32, f32),
+    ) -> Self {
+        let shape = data.shape().to_vec();
+        Self::from_3d_indices(header, data, views, |axis| {
+            build_indices(&shape, nb_slices, axis, range)
+        })
+    }
+
+    /// Slices the volume at `index` along the axis of the view.
+    pub fn from_3d_at(header: NiftiHeader, data: Array3<f32>, view: View, index: usize) -> Self {
+        Self::from_3d_indices(header, data, &[view], |_| vec![index])
+    }
+
+    /// The 3 orthogonal slices through the voxel `crosshair`, drawn in a single scene.
+    pub fn from_3d_crosshair(
+        header: NiftiHeader,
+        data: Array3<f32>,
+        crosshair: [usize; 3],
+    ) -> Self {
+        Self::from_3d_indices(header, data, &CROSSHAIR_VIEWS, |axis| {
+            vec![crosshair[axis as usize]]
+        })
+    }
+
+    fn from_3d_indices(
+        header: NiftiHeader,
+        data: Array3<f32>,
+        views: &[View],
+        indices: impl Fn(Axis) -> Vec<usize>,
     ) -> Self {
         // Rescale whatever we've got to u8
         let min_value = data.fold(f32::MAX, |acc, &v| f32::min(acc, v));
@@ -125,10 +170,11 @@ impl Slicer {
 
         // I tried doing a views.flat_map(indices.map()) but I have a borrow checker problem that
         // I'm unsable to fix.
-        let mut slices = Vec::with_capacity(views.len() * nb_slices);
+        let mut slices = Vec::with_capacity(views.len());
         for &view in views {
             let axis = view.clone().axis();
-            for idx in build_indices(&data, nb_slices, axis, range) {
+            let axis_spacing = [spacing.0, spacing.1, spacing.2][axis as usize];
+            for idx in indices(axis) {
                 let slice: Array2<u8> = data
                     .index_axis(ndarray::Axis(axis as usize), idx)
                     .mapv(rescale);
@@ -140,10 +186,74 @@ impl Slicer {
                 data_c.assign(&slice);
 
                 slices.push(Slice {
-                    data: data_c,
+                    data: SliceData::Gray(data_c),
+                    view,
+                    index: idx,
+                    depth: (idx as f32 + 0.5) * axis_spacing,
+                });
+            }
+        }
+
+        Slicer {
+            _header: header,
+            _spacing: spacing,
+            slices,
+        }
+    }
+
+    /// Slices a volume of colors in [0, 1], which are clamped.
+    pub fn from_color(
+        header: NiftiHeader,
+        data: Array4<f32>,
+        nb_slices: usize,
+        views: &[View],
+        range: (f32, f32),
+    ) -> Self {
+        let shape = data.shape()[..3].to_vec();
+        Self::from_color_indices(header, data, views, |axis| {
+            build_indices(&shape, nb_slices, axis, range)
+        })
+    }
+
+    /// Same as [`Slicer::from_3d_crosshair`], for a volume of colors.
+    pub fn from_color_crosshair(
+        header: NiftiHeader,
+        data: Array4<f32>,
+        crosshair: [usize; 3],
+    ) -> Self {
+        Self::from_color_indices(header, data, &CROSSHAIR_VIEWS, |axis| {
+            vec![crosshair[axis as usize]]
+        })
+    }
+
+    fn from_color_indices(
+        header: NiftiHeader,
+        data: Array4<f32>,
+        views: &[View],
+        indices: impl Fn(Axis) -> Vec<usize>,
+    ) -> Self {
+        let spacing = (header.pixdim[1], header.pixdim[2], header.pixdim[3]);
+
+        let mut slices = Vec::with_capacity(views.len());
+        for &view in views {
+            let axis = view.axis();
+            let axis_spacing = [spacing.0, spacing.1, spacing.2][axis as usize];
+            for idx in indices(axis) {
+                let slice = data.index_axis(ndarray::Axis(axis as usize), idx);
+                let (width, height, _) = slice.dim();
+
+                // Same as the gray slices, with the channels stored first
+                let mut data_c = Array3::zeros((4, width, height).f()).permuted_axes([1, 2, 0]);
+                data_c
+                    .slice_mut(s![.., .., ..3])
+                    .assign(&slice.mapv(|c| (c.clamp(0., 1.) * 255.).round() as u8));
+                data_c.slice_mut(s![.., .., 3]).fill(255);
+
+                slices.push(Slice {
+                    data: SliceData::Color(data_c),
                     view,
                     index: idx,
-                    _depth: 0.0, // TODO Spacing * index
+                    depth: (idx as f32 + 0.5) * axis_spacing,
                 });
             }
         }
@@ -156,14 +266,25 @@ impl Slicer {
     }
 }
 
+/// Name of the output images of a scene, from its slices.
+pub fn scene_name(slices: &[Slice]) -> String {
+    match slices {
+        [slice] => format!("{}_{}", slice.view.name(), slice.index),
+        _ => {
+            let indices: Vec<String> = slices.iter().map(|s| s.index.to_string()).collect();
+            format!("crosshair_{}", indices.join("_"))
+        }
+    }
+}
+
 fn build_indices(
-    data: &Array3<f32>,
+    shape: &[usize],
     mut nb_slices: usize,
     axis: Axis,
     range: (f32, f32),
 ) -> Vec<usize> {
     // TODO We can calculate the bbox to avoid the blank zones
-    let width = data.shape()[axis as usize] as f32;
+    let width = shape[axis as usize] as f32;
     let max_idx = width * range.1;
     let min_idx = width * range.0;
 
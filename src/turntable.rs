@@ -0,0 +1,142 @@
+use std::{
+    f32::consts::TAU,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use glam::{Mat4, Vec3};
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame,
+};
+
+use crate::{
+    file,
+    graphics::Context,
+    inputs::Args,
+    slicer::{self, Slice},
+    Image,
+};
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum TurntableAxis {
+    /// Left to right
+    Right,
+    /// Posterior to anterior
+    Anterior,
+    /// Inferior to superior
+    Superior,
+}
+
+impl TurntableAxis {
+    /// In voxel space, like the views.
+    fn vector(&self) -> Vec3 {
+        match self {
+            TurntableAxis::Right => Vec3::X,
+            TurntableAxis::Anterior => Vec3::Y,
+            TurntableAxis::Superior => Vec3::Z,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum AnimationFormat {
+    Gif,
+    Apng,
+    /// Numbered PNG images
+    Frames,
+}
+
+/// Output of the frames, which are written as soon as they are rendered.
+enum Animation {
+    Gif(GifEncoder<BufWriter<File>>, Delay),
+    Apng(png::Writer<BufWriter<File>>),
+    Frames(PathBuf),
+}
+
+/// Renders a full turn of the volume around `axis`, with the slices and the fibers, in
+/// `nb_frames` frames. The fibers stay on the GPU between the frames.
+pub fn render(
+    args: &Args,
+    graphics: &mut Context,
+    slices: &[Slice],
+    axis: TurntableAxis,
+    nb_frames: u32,
+    format: AnimationFormat,
+    fps: u16,
+) {
+    let name = format!("turntable_{}", slicer::scene_name(slices));
+    let mut animation = Animation::new(args, &name, format, nb_frames, fps);
+
+    for frame in 0..nb_frames {
+        let angle = TAU * frame as f32 / nb_frames as f32;
+        graphics.set_scene_rotation(Mat4::from_axis_angle(axis.vector(), angle));
+        animation.push(graphics.process_scene(slices), frame);
+    }
+    animation.finish();
+    graphics.set_scene_rotation(Mat4::IDENTITY);
+}
+
+impl Animation {
+    fn new(args: &Args, name: &str, format: AnimationFormat, nb_frames: u32, fps: u16) -> Self {
+        let (width, height) = (args.output_size[0], args.output_size[1]);
+        match format {
+            AnimationFormat::Gif => {
+                let file = create_file(&args.output.join(format!("{name}.gif")));
+                let mut encoder = GifEncoder::new_with_speed(file, 10);
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .expect("The GIF header is writable");
+                Animation::Gif(encoder, Delay::from_numer_denom_ms(1000, fps.into()))
+            }
+            AnimationFormat::Apng => {
+                let path = args.output.join(format!("{name}.png"));
+                let mut encoder = png::Encoder::new(create_file(&path), width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder
+                    .set_animated(nb_frames, 0)
+                    .and_then(|_| encoder.set_frame_delay(1, fps))
+                    .expect("The animation has at least one frame");
+                let writer = encoder.write_header().unwrap_or_else(|error| {
+                    panic!("Failed to write the APNG at {path:?}: {error}")
+                });
+                Animation::Apng(writer)
+            }
+            AnimationFormat::Frames => Animation::Frames(args.output.join(name)),
+        }
+    }
+
+    fn push(&mut self, image: Image, index: u32) {
+        match self {
+            Animation::Gif(encoder, delay) => {
+                encoder
+                    .encode_frame(Frame::from_parts(image, 0, 0, *delay))
+                    .unwrap_or_else(|error| panic!("Failed to encode the GIF frame: {error}"));
+            }
+            Animation::Apng(writer) => writer
+                .write_image_data(image.as_raw())
+                .unwrap_or_else(|error| panic!("Failed to encode the APNG frame: {error}")),
+            Animation::Frames(prefix) => {
+                let path = format!("{}_{index:03}.png", prefix.display());
+                file::save_image(image, Path::new(&path));
+            }
+        }
+    }
+
+    fn finish(self) {
+        if let Animation::Apng(writer) = self {
+            writer
+                .finish()
+                .unwrap_or_else(|error| panic!("Failed to finish the APNG: {error}"));
+        }
+    }
+}
+
+fn create_file(path: &Path) -> BufWriter<File> {
+    match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(error) => panic!("Failed to create {path:?}: {error}"),
+    }
+}